@@ -0,0 +1,265 @@
+use proc_macro2::Span;
+use syn::meta::ParseNestedMeta;
+use syn::{Attribute, Expr, Ident, LitStr, Path, Token, Visibility};
+
+/// Options set on the struct through `#[builder(...)]` attributes
+#[derive(Default)]
+pub(crate) struct StructAttributes {
+    /// `into` or `try_into`: conversion applied to every field's setter
+    pub(crate) conversion: Option<Conversion>,
+    /// `validate = path`: function validating the built struct
+    pub(crate) validate: Option<Path>,
+    /// `pattern = "owned"` or `pattern = "mutable"`: how setters take the
+    /// builder
+    pub(crate) pattern: Pattern,
+    /// `name = "..."`: name of the builder struct
+    pub(crate) name: Option<Ident>,
+    /// `vis = "..."`: visibility of the builder struct, its error and its
+    /// entry points
+    pub(crate) vis: Option<Visibility>,
+    /// `prefix = "..."`: prefix of the setters' names
+    pub(crate) prefix: Option<String>,
+    /// `build_fn = "..."`: name of the build function
+    pub(crate) build_fn: Option<Ident>,
+    /// `const`: the setters and the build function are `const fn`, the
+    /// build function panics instead of returning an error. Setting a field
+    /// twice leaks its previous value, which can't be dropped in a const
+    /// context.
+    pub(crate) constant: bool,
+    /// `serde`: the builder implements `serde::Deserialize` and can be merged
+    /// with another builder, the build function checks every field at
+    /// runtime
+    pub(crate) serde: bool,
+    /// `#[builder_defaults]`: every field falls back to its default value,
+    /// the build function is available whatever the builder's state
+    pub(crate) use_defaults: bool,
+    /// The keys of the options, to report errors on them
+    keys: Vec<Ident>,
+}
+
+/// How setters take the builder
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(crate) enum Pattern {
+    /// Setters consume the builder and track the set fields in its type, the
+    /// build function is only available once every required field is set
+    #[default]
+    Owned,
+    /// Setters take the builder by mutable reference, the build function
+    /// clones the fields and checks the required ones at runtime
+    Mutable,
+}
+
+/// Options set on a field through `#[builder(...)]` attributes
+#[derive(Default)]
+pub(crate) struct FieldAttributes {
+    /// `default` or `default = expr`: the field can be left unset and falls
+    /// back to `Default::default()` or the provided expression
+    pub(crate) default: Option<Option<Expr>>,
+    /// `into` or `try_into`: conversion applied to the field's setter
+    pub(crate) conversion: Option<Conversion>,
+    /// `setter = "name"`: name used for the field's setters instead of its
+    /// identifier or index
+    pub(crate) setter: Option<Ident>,
+    /// `each = "name"`: name of the setter adding a single item to a
+    /// collection field
+    pub(crate) each: Option<Ident>,
+    /// `validate = path`: function validating the field's value
+    pub(crate) validate: Option<Path>,
+    /// `nested`: the field's type also derives `Builder`, its setter drives
+    /// that builder
+    pub(crate) nested: bool,
+    /// `skip` or `skip = expr`: the field has no setter and is always set to
+    /// `Default::default()` or the provided expression
+    pub(crate) skip: Option<Option<Expr>>,
+    /// `secret`: the field's value is hidden in the builder's `Debug` output
+    pub(crate) secret: bool,
+    /// `group = "name"`: group of fields the field belongs to
+    pub(crate) group: Option<Ident>,
+    /// `exclusive` or `at_least_one`: constraint on the field's group
+    pub(crate) group_constraint: Option<GroupConstraint>,
+}
+
+/// Constraint checked at compile time on the fields of a group
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum GroupConstraint {
+    /// At most one field of the group can be set
+    Exclusive,
+    /// At least one field of the group must be set
+    AtLeastOne,
+}
+
+/// Conversion accepted by a setter for its value
+#[derive(Clone, Copy)]
+pub(crate) enum Conversion {
+    /// The setter takes any `impl Into<T>`
+    Into,
+    /// The setter takes any `TryInto<T>` and returns the conversion error
+    TryInto,
+}
+
+impl StructAttributes {
+    /// Parse the `#[builder(...)]` attributes of the struct
+    pub(crate) fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut attributes = StructAttributes::default();
+
+        for attr in attrs.iter().filter(|a| a.path().is_ident("builder")) {
+            attr.parse_nested_meta(|meta| attributes.parse_meta(meta))?;
+        }
+        // Check if the `builder_defaults` macro is active
+        attributes.use_defaults = attrs.iter().any(|a| a.path().is_ident("builder_defaults"));
+
+        Ok(attributes)
+    }
+
+    /// Parse a single option, from a `#[builder(...)]` attribute of a struct
+    /// or from the arguments of the `#[builder(...)]` attribute of a function
+    pub(crate) fn parse_meta(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        self.keys.extend(meta.path.get_ident().cloned());
+        if let Some(conversion) = Conversion::parse(&meta) {
+            self.conversion = Some(conversion);
+            Ok(())
+        } else if meta.path.is_ident("validate") {
+            self.validate = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("pattern") {
+            let pattern = meta.value()?.parse::<LitStr>()?;
+            self.pattern = match pattern.value().as_str() {
+                "owned" => Pattern::Owned,
+                "mutable" => Pattern::Mutable,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        pattern,
+                        "expected \"owned\" or \"mutable\"",
+                    ))
+                }
+            };
+            Ok(())
+        } else if meta.path.is_ident("name") {
+            let name = meta.value()?.parse::<LitStr>()?;
+            self.name = Some(parse_ident(&name)?);
+            Ok(())
+        } else if meta.path.is_ident("vis") {
+            let vis = meta.value()?.parse::<LitStr>()?;
+            self.vis = Some(vis.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("prefix") {
+            let prefix = meta.value()?.parse::<LitStr>()?;
+            // The prefix is followed by the field's name in the setters' names
+            let setter = format!("{}x", prefix.value());
+            if prefix.value().contains('#') || syn::parse_str::<Ident>(&setter).is_err() {
+                return Err(syn::Error::new_spanned(
+                    &prefix,
+                    format!("`{}` is not a valid setter prefix", prefix.value()),
+                ));
+            }
+            self.prefix = Some(prefix.value());
+            Ok(())
+        } else if meta.path.is_ident("serde") {
+            self.serde = true;
+            Ok(())
+        } else if meta.path.is_ident("const") {
+            self.constant = true;
+            Ok(())
+        } else if meta.path.is_ident("build_fn") {
+            let name = meta.value()?.parse::<LitStr>()?;
+            self.build_fn = Some(parse_ident(&name)?);
+            Ok(())
+        } else {
+            Err(meta.error("unknown builder attribute"))
+        }
+    }
+
+    /// Get the span of an option's key, or the macro's call site if it isn't
+    /// set
+    pub(crate) fn key_span(&self, key: &str) -> Span {
+        self.keys
+            .iter()
+            .rev()
+            .find(|k| *k == key)
+            .map_or_else(Span::call_site, Ident::span)
+    }
+}
+
+impl FieldAttributes {
+    /// Parse the `#[builder(...)]` attributes of a field
+    pub(crate) fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut attributes = FieldAttributes::default();
+
+        for attr in attrs.iter().filter(|a| a.path().is_ident("builder")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("default") {
+                    let expr = if meta.input.peek(Token![=]) {
+                        Some(meta.value()?.parse()?)
+                    } else {
+                        None
+                    };
+                    attributes.default = Some(expr);
+                    Ok(())
+                } else if let Some(conversion) = Conversion::parse(&meta) {
+                    attributes.conversion = Some(conversion);
+                    Ok(())
+                } else if meta.path.is_ident("setter") {
+                    let name = meta.value()?.parse::<LitStr>()?;
+                    attributes.setter = Some(parse_ident(&name)?);
+                    Ok(())
+                } else if meta.path.is_ident("each") {
+                    let name = meta.value()?.parse::<LitStr>()?;
+                    attributes.each = Some(parse_ident(&name)?);
+                    Ok(())
+                } else if meta.path.is_ident("validate") {
+                    attributes.validate = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("nested") {
+                    attributes.nested = true;
+                    Ok(())
+                } else if meta.path.is_ident("secret") {
+                    attributes.secret = true;
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    let expr = if meta.input.peek(Token![=]) {
+                        Some(meta.value()?.parse()?)
+                    } else {
+                        None
+                    };
+                    attributes.skip = Some(expr);
+                    Ok(())
+                } else if meta.path.is_ident("group") {
+                    let name = meta.value()?.parse::<LitStr>()?;
+                    attributes.group = Some(parse_ident(&name)?);
+                    Ok(())
+                } else if meta.path.is_ident("exclusive") {
+                    attributes.group_constraint = Some(GroupConstraint::Exclusive);
+                    Ok(())
+                } else if meta.path.is_ident("at_least_one") {
+                    attributes.group_constraint = Some(GroupConstraint::AtLeastOne);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown builder attribute"))
+                }
+            })?;
+        }
+
+        Ok(attributes)
+    }
+}
+
+impl Conversion {
+    /// Parse the conversion from an `into` or `try_into` key
+    fn parse(meta: &ParseNestedMeta) -> Option<Self> {
+        if meta.path.is_ident("into") {
+            Some(Conversion::Into)
+        } else if meta.path.is_ident("try_into") {
+            Some(Conversion::TryInto)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse an identifier from a string literal, pointing at the literal if it
+/// isn't a valid identifier
+pub(crate) fn parse_ident(lit: &LitStr) -> syn::Result<Ident> {
+    lit.parse().map_err(|_| {
+        syn::Error::new_spanned(lit, format!("`{}` is not a valid identifier", lit.value()))
+    })
+}
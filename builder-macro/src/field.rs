@@ -0,0 +1,424 @@
+use crate::attributes::{parse_ident, Conversion, FieldAttributes, GroupConstraint};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{
+    Attribute, Expr, ExprLit, Field, GenericArgument, Ident, Index, Lit, Member, MetaNameValue,
+    Path, PathArguments, Type,
+};
+
+/// A field of the struct the builder is derived for
+pub(crate) struct BuilderField {
+    /// The identifier of the builder's field. This is the field's identifier
+    /// for named fields, `field_{index}` for unnamed ones.
+    pub(crate) ident: Ident,
+    /// The field's member, to access it on the struct
+    pub(crate) member: Member,
+    /// The field's type
+    pub(crate) ty: Type,
+    /// The name used to generate the field's setter
+    pub(crate) setter_name: String,
+    /// The `#[rename]` attribute or `setter` name renaming the field's
+    /// setters, if any
+    pub(crate) rename: Option<proc_macro2::TokenStream>,
+    /// How the field takes part in the builder's typestate
+    pub(crate) kind: FieldKind,
+    /// Conversion accepted by the field's setter
+    pub(crate) conversion: Option<Conversion>,
+    /// Setter adding a single item to the field's collection
+    pub(crate) each: Option<EachSetter>,
+    /// Function validating the field's value before building
+    pub(crate) validate: Option<Path>,
+    /// Builder of the field's type, driven by the field's setter
+    pub(crate) nested: Option<NestedBuilder>,
+    /// Whether the field's value is hidden in the builder's `Debug` output
+    pub(crate) secret: bool,
+    /// Group of fields the field belongs to
+    pub(crate) group: Option<Ident>,
+    /// Constraint on the field's group, if set on this field
+    pub(crate) group_constraint: Option<GroupConstraint>,
+    /// The field's doc comments and `#[deprecated]` attribute, forwarded to
+    /// its setters
+    pub(crate) docs: Vec<Attribute>,
+    /// The field's `#[cfg]` attributes, forwarded to everything generated
+    /// for the field
+    pub(crate) cfgs: Vec<Attribute>,
+}
+
+/// Builder of a field's type, generated from a `#[builder(nested)]`
+/// attribute. The field's type must derive `Builder` as well, the paths of
+/// the generated items are inferred from the type's path.
+pub(crate) struct NestedBuilder {
+    /// The inner builder, in its initial state
+    pub(crate) builder: Type,
+    /// The trait implemented by the inner builder once it can be built
+    pub(crate) ready: Path,
+    /// The error returned when building the inner builder
+    pub(crate) error: Path,
+}
+
+/// Setter adding a single item to a collection field, generated from a
+/// `#[builder(each = "name")]` attribute
+pub(crate) struct EachSetter {
+    /// The setter's name
+    pub(crate) name: Ident,
+    /// The type of the items: a single type for lists and sets, the key and
+    /// value types for maps
+    pub(crate) item: Vec<Type>,
+}
+
+/// How a field takes part in the builder's typestate
+pub(crate) enum FieldKind {
+    /// The field must be set before the struct can be built
+    Required,
+    /// The field is an `Option<T>`, holding the inner type `T`. It can be
+    /// left unset.
+    Optional(Box<Type>),
+    /// The field has a `#[builder(default)]` attribute. It can be left unset
+    /// and falls back to the provided expression, or `Default::default()`.
+    Default(Option<Expr>),
+    /// The field has a `#[builder(skip)]` attribute. It has no setter and is
+    /// always set to the provided expression, or `Default::default()`.
+    Skipped(Option<Expr>),
+}
+
+impl BuilderField {
+    /// Create the builder field from the struct's field at the given index
+    pub(crate) fn new(index: usize, field: Field) -> syn::Result<Self> {
+        let attributes = FieldAttributes::parse(&field.attrs)?;
+        let rename_attr = field.attrs.iter().find(|a| a.path().is_ident("rename"));
+        let (setter_name, rename) = match (&attributes.setter, rename_attr) {
+            (Some(setter), Some(attr)) => {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    format!("rename can't be combined with setter `{}`", setter),
+                ))
+            }
+            (Some(setter), None) => (setter.unraw().to_string(), Some(quote!(#setter))),
+            (None, rename) => (get_field_name(&field, index)?, rename.map(|a| quote!(#a))),
+        };
+        let (ident, member) = match &field.ident {
+            Some(ident) => (ident.clone(), Member::Named(ident.clone())),
+            None => (
+                format_ident!("field_{}", index),
+                Member::Unnamed(Index::from(index)),
+            ),
+        };
+        let each = attributes
+            .each
+            .map(|name| {
+                // Adding an item doesn't track the state of the field's group
+                if attributes.group.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        &name,
+                        "each can't be combined with group",
+                    ));
+                }
+                // The item setter extends the collection, which an `Option` isn't
+                if option_inner_type(&field.ty).is_some() {
+                    return Err(syn::Error::new_spanned(
+                        &field.ty,
+                        "each is not available for optional collections",
+                    ));
+                }
+                let item = collection_item_types(&field.ty).ok_or_else(|| {
+                    syn::Error::new_spanned(
+                        &field.ty,
+                        "each is only available for collections with generic arguments",
+                    )
+                })?;
+                syn::Result::Ok(EachSetter { name, item })
+            })
+            .transpose()?;
+
+        // Collections with an item setter default to being empty
+        let default = attributes.default.or_else(|| each.as_ref().map(|_| None));
+        let kind = match (attributes.skip, default, option_inner_type(&field.ty)) {
+            (Some(skip), None, _) if !attributes.nested && attributes.group.is_none() => {
+                FieldKind::Skipped(skip)
+            }
+            (Some(_), _, _) => {
+                return Err(syn::Error::new_spanned(
+                    &field,
+                    "skip can't be combined with default, each, nested or group",
+                ))
+            }
+            (None, Some(default), _) => FieldKind::Default(default),
+            (None, None, Some(inner)) => FieldKind::Optional(Box::new(inner.clone())),
+            (None, None, None) => FieldKind::Required,
+        };
+
+        let docs = field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("doc") || a.path().is_ident("deprecated"))
+            .cloned()
+            .collect();
+        let cfgs = field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("cfg"))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut builder_field = BuilderField {
+            ident,
+            member,
+            ty: field.ty,
+            setter_name,
+            rename,
+            kind,
+            conversion: attributes.conversion,
+            each,
+            validate: attributes.validate,
+            nested: None,
+            secret: attributes.secret,
+            group: attributes.group,
+            group_constraint: attributes.group_constraint,
+            docs,
+            cfgs,
+        };
+        // Fields with a state have a type parameter in the builder, which
+        // can't be configured out, and unnamed fields can't be configured out
+        // when constructing the struct
+        if let Some(cfg) = builder_field.cfgs.first() {
+            if builder_field.has_state() || matches!(builder_field.member, Member::Unnamed(_)) {
+                return Err(syn::Error::new_spanned(
+                    cfg,
+                    "cfg is only available for named fields that can be left unset",
+                ));
+            }
+        }
+        if attributes.nested {
+            let value_ty = builder_field.value_ty();
+            let nested = nested_builder(value_ty).ok_or_else(|| {
+                syn::Error::new_spanned(value_ty, "nested is only available for path types")
+            })?;
+            builder_field.nested = Some(nested);
+        }
+
+        Ok(builder_field)
+    }
+
+    /// Whether the field must be set before building
+    pub(crate) fn is_required(&self) -> bool {
+        matches!(self.kind, FieldKind::Required)
+    }
+
+    /// Whether the field is skipped: it has no setter nor storage in the
+    /// builder
+    pub(crate) fn is_skipped(&self) -> bool {
+        matches!(self.kind, FieldKind::Skipped(_))
+    }
+
+    /// Whether the field has a state type parameter in the builder: required
+    /// fields and fields belonging to a group do
+    pub(crate) fn has_state(&self) -> bool {
+        self.is_required() || self.group.is_some()
+    }
+
+    /// Get the type of the value taken by the field's setter
+    pub(crate) fn value_ty(&self) -> &Type {
+        match &self.kind {
+            FieldKind::Optional(inner) => inner,
+            FieldKind::Required | FieldKind::Default(_) | FieldKind::Skipped(_) => &self.ty,
+        }
+    }
+
+    /// Generate the type storing the field in the builder. `Option<T>` fields
+    /// are already wrapped in an `Option` and are stored as is. Nested fields
+    /// store the result of their builder.
+    pub(crate) fn storage_ty(&self) -> proc_macro2::TokenStream {
+        let ty = &self.ty;
+        match (&self.kind, &self.nested) {
+            (_, Some(nested)) => {
+                let value_ty = self.value_ty();
+                let error = &nested.error;
+                quote!(Option<Result<#value_ty, #error>>)
+            }
+            (FieldKind::Optional(_), None) => quote!(#ty),
+            (_, None) => quote!(Option<#ty>),
+        }
+    }
+
+    /// Generate the names of the field's getter and of the query checking
+    /// whether the field is set. The getter gets a `get_` prefix when it
+    /// would clash with the setter or one of the reserved methods, or isn't
+    /// a valid identifier.
+    pub(crate) fn getter_names(&self, setter_prefix: &str, reserved: &[String]) -> (Ident, Ident) {
+        let name = &self.setter_name;
+        let prefixed = setter_prefix.is_empty()
+            || reserved.contains(name)
+            || syn::parse_str::<Ident>(name).is_err();
+        let getter = match prefixed {
+            true => format_ident!("get_{}", name),
+            false => format_ident!("{}", name),
+        };
+        (getter, format_ident!("has_{}", name))
+    }
+
+    /// Generate the state type parameter identifier for the field
+    pub(crate) fn state_param(&self) -> Ident {
+        format_ident!("__{}", self.camel_case_name())
+    }
+
+    /// Generate the error variant identifier for the field being missing
+    pub(crate) fn missing_variant(&self) -> Ident {
+        format_ident!("Missing{}", self.camel_case_name())
+    }
+
+    /// Generate the error variant identifier for the field failing validation
+    pub(crate) fn invalid_variant(&self) -> Ident {
+        format_ident!("Invalid{}", self.camel_case_name())
+    }
+
+    /// Generate the error variant identifier for the field's builder failing
+    pub(crate) fn nested_variant(&self) -> Ident {
+        format_ident!("{}Builder", self.camel_case_name())
+    }
+
+    /// Get the name of the field as declared in the struct: its identifier or
+    /// its index
+    pub(crate) fn display_name(&self) -> String {
+        match &self.member {
+            Member::Named(ident) => ident.to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        }
+    }
+
+    /// Get the field's identifier in upper camel case
+    fn camel_case_name(&self) -> String {
+        let field = self.ident.to_string();
+        to_upper_camel_case(field.trim_start_matches("r#"))
+    }
+}
+
+/// Get the inner type `T` if the type is an `Option<T>`
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    if path.qself.is_some() {
+        return None;
+    }
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the items generated by the builder derive of a type from the type's
+/// path
+fn nested_builder(ty: &Type) -> Option<NestedBuilder> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    if path.qself.is_some() {
+        return None;
+    }
+
+    let with_suffix = |suffix: &str, keep_args: bool| {
+        let mut path = path.path.clone();
+        let segment = path.segments.last_mut()?;
+        segment.ident = format_ident!("{}{}", segment.ident, suffix);
+        if !keep_args {
+            segment.arguments = PathArguments::None;
+        }
+        Some(path)
+    };
+
+    Some(NestedBuilder {
+        builder: Type::Path(syn::TypePath {
+            qself: None,
+            path: with_suffix("Builder", true)?,
+        }),
+        ready: with_suffix("BuilderReady", false)?,
+        error: with_suffix("BuilderError", false)?,
+    })
+}
+
+/// Get the types of the items of a collection: the key and value types for
+/// maps, the first generic argument for any other collection.
+fn collection_item_types(ty: &Type) -> Option<Vec<Type>> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    let count = if segment.ident.to_string().ends_with("Map") {
+        2
+    } else {
+        1
+    };
+
+    let types = args
+        .args
+        .iter()
+        .filter_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty.clone()),
+            _ => None,
+        })
+        .take(count)
+        .collect::<Vec<_>>();
+    (types.len() == count).then_some(types)
+}
+
+/// Get the field name. Field's identifier, or its index for unnamed fields,
+/// by default or the rename attribute if it exists.
+fn get_field_name(f: &Field, index: usize) -> syn::Result<String> {
+    let rename_attr = f.attrs.iter().find(|attr| attr.path().is_ident("rename"));
+    match (rename_attr, &f.ident) {
+        (Some(attr), Some(_)) => get_renamed_field(attr).map(|i| i.to_string()),
+        (Some(attr), None) => Err(syn::Error::new_spanned(
+            attr,
+            "rename is only available for named fields, use `#[builder(setter = \"name\")]` instead",
+        )),
+        (None, Some(ident)) => Ok(ident.unraw().to_string()),
+        (None, None) => Ok(index.to_string()),
+    }
+}
+
+/// Get the renamed field from the attribute
+fn get_renamed_field(attr: &Attribute) -> syn::Result<Ident> {
+    match attr.meta {
+        syn::Meta::List(ref list) => list.parse_args().map_err(|_| {
+            syn::Error::new_spanned(
+                &list.tokens,
+                format!("`{}` is not a valid identifier", list.tokens),
+            )
+        }),
+        syn::Meta::NameValue(MetaNameValue {
+            value:
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(ref str),
+                    ..
+                }),
+            ..
+        }) => parse_ident(str),
+        _ => Err(syn::Error::new_spanned(
+            attr,
+            "expected `#[rename = \"name\"]` or `#[rename(name)]`",
+        )),
+    }
+}
+
+/// Convert a snake case identifier to upper camel case
+pub(crate) fn to_upper_camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -0,0 +1,378 @@
+use crate::attributes::{Pattern, StructAttributes};
+use crate::field::to_upper_camel_case;
+use crate::Builder;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    Field, FieldMutability, Fields, FieldsNamed, FnArg, GenericParam, Generics, Ident, ImplItem,
+    ItemFn, ItemImpl, Lifetime, LifetimeParam, Meta, ParenthesizedGenericArguments, Pat,
+    ReturnType, Signature, Token, Type, TypeBareFn, TypeImplTrait, TypeReference, Visibility,
+};
+
+/// The function called with the arguments collected by a builder
+pub(crate) struct FnTarget {
+    /// `async` if the function is asynchronous, the builder then awaits it
+    pub(crate) asyncness: Option<Token![async]>,
+    /// The function's return type
+    pub(crate) output: Type,
+    /// The impl block declaring the function, if it's a method
+    pub(crate) method: Option<Method>,
+}
+
+/// A method or associated function whose arguments are collected by a
+/// builder, called on its type
+pub(crate) struct Method {
+    /// The type the method is implemented for
+    pub(crate) self_ty: Type,
+    /// The name of that type
+    pub(crate) self_name: Ident,
+    /// The type of the method's receiver, moved into the builder
+    pub(crate) receiver: Option<Type>,
+    /// The generics of the method returning the builder, on top of the impl
+    /// block's ones
+    pub(crate) entry_generics: Generics,
+}
+
+/// Generate the builder for the arguments of a function, followed by the
+/// function itself with the builder's attributes removed from its arguments
+pub(crate) fn expand(
+    attributes: StructAttributes,
+    mut function: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // The builder struct can't be declared inside an impl block
+    if let Some(receiver) = function.sig.receiver() {
+        return Err(syn::Error::new_spanned(
+            receiver,
+            "builder is only available on methods through their impl block, annotate it with `#[builder_macro::builder]` and the method with `#[builder]`",
+        ));
+    }
+
+    let builder = fn_builder(attributes, &function.sig, &function.vis, None)?.expand();
+    strip_attributes(&mut function.sig);
+
+    Ok(quote!(
+        #function
+        #builder
+    ))
+}
+
+/// Generate the builders for the methods of an impl block marked with
+/// `#[builder(...)]`, declared after the impl block. Each method gets a
+/// `{method}_builder` method in the impl block, taking its receiver.
+pub(crate) fn expand_impl(mut item_impl: ItemImpl) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some((_, path, _)) = &item_impl.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "builder is only available for inherent impl blocks",
+        ));
+    }
+
+    let mut builders = Vec::new();
+    let mut entries = Vec::new();
+    for item in item_impl.items.iter_mut() {
+        let ImplItem::Fn(method) = item else {
+            continue;
+        };
+        let Some(index) = method
+            .attrs
+            .iter()
+            .position(|a| a.path().is_ident("builder"))
+        else {
+            continue;
+        };
+
+        let attr = method.attrs.remove(index);
+        let mut attributes = StructAttributes::default();
+        if let Meta::List(_) = attr.meta {
+            attr.parse_nested_meta(|meta| attributes.parse_meta(meta))?;
+        }
+        let builder = fn_builder(
+            attributes,
+            &method.sig,
+            &method.vis,
+            Some((&item_impl.self_ty, &item_impl.generics)),
+        )?;
+        entries.push(ImplItem::Verbatim(builder.method_entry()));
+        builders.push(builder.expand());
+        strip_attributes(&mut method.sig);
+    }
+    item_impl.items.extend(entries);
+
+    Ok(quote!(
+        #item_impl
+        #(#builders)*
+    ))
+}
+
+/// Create the builder for the arguments of a function, or of a method of the
+/// provided type and impl block generics
+fn fn_builder(
+    mut attributes: StructAttributes,
+    sig: &Signature,
+    vis: &Visibility,
+    impl_block: Option<(&Type, &Generics)>,
+) -> syn::Result<Builder> {
+    let unsupported = [
+        ("pattern", attributes.pattern == Pattern::Mutable),
+        ("serde", attributes.serde),
+        ("const", attributes.constant),
+        ("validate", attributes.validate.is_some()),
+    ];
+    if let Some((key, _)) = unsupported.iter().find(|(_, set)| *set) {
+        return Err(syn::Error::new(
+            attributes.key_span(key),
+            "function builders don't support the mutable pattern, serde, const or validate",
+        ));
+    }
+
+    // `Self` isn't available outside of the impl block
+    let mut sig = sig.clone();
+    if let Some((self_ty, _)) = impl_block {
+        ReplaceSelf(self_ty).visit_signature_mut(&mut sig);
+    }
+
+    // Each argument becomes a named field of the builder, the receiver is
+    // stored on its own
+    let mut fields = Punctuated::<Field, Token![,]>::new();
+    let mut lifetimes = ElidedLifetimes::default();
+    let mut receiver = None;
+    for input in &sig.inputs {
+        let arg = match input {
+            FnArg::Receiver(r) => {
+                let ty = lifetimes.name_inputs(&r.ty)?;
+                if let Type::Reference(reference) = &ty {
+                    lifetimes.receiver = reference.lifetime.clone();
+                }
+                receiver = Some(ty);
+                continue;
+            }
+            FnArg::Typed(arg) => arg,
+        };
+        let Pat::Ident(pat) = &*arg.pat else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "builder is only available for arguments bound to an identifier",
+            ));
+        };
+        if let Some(cfg) = arg.attrs.iter().find(|a| a.path().is_ident("cfg")) {
+            return Err(syn::Error::new_spanned(
+                cfg,
+                "cfg is not available for the arguments of function builders",
+            ));
+        }
+        fields.push(Field {
+            attrs: arg.attrs.clone(),
+            vis: Visibility::Inherited,
+            mutability: FieldMutability::None,
+            ident: Some(pat.ident.clone()),
+            colon_token: Some(arg.colon_token),
+            ty: lifetimes.name_inputs(&arg.ty)?,
+        });
+    }
+    let output = match &sig.output {
+        ReturnType::Default => syn::parse_quote!(()),
+        ReturnType::Type(_, ty) => lifetimes.name_output(ty),
+    };
+
+    // Builders of methods are named after their type as well
+    let fn_name = &sig.ident;
+    let self_name = impl_block
+        .map(|(self_ty, _)| type_name(self_ty))
+        .transpose()?;
+    let camel_name = to_upper_camel_case(&fn_name.to_string());
+    attributes.name.get_or_insert_with(|| match &self_name {
+        Some(self_name) => format_ident!("{}{}Builder", self_name, camel_name),
+        None => format_ident!("{}Builder", camel_name),
+    });
+    attributes.prefix.get_or_insert_with(String::new);
+    attributes
+        .build_fn
+        .get_or_insert_with(|| format_ident!("call"));
+
+    // The builder declares the generics of the impl block and of the
+    // function, and the lifetimes elided in the arguments' types
+    let mut entry_generics = sig.generics.clone();
+    for lifetime in lifetimes.named.iter().rev() {
+        let param = LifetimeParam::new(lifetime.clone());
+        entry_generics
+            .params
+            .insert(0, GenericParam::Lifetime(param));
+    }
+    let generics = match impl_block {
+        Some((_, impl_generics)) => merge_generics(impl_generics, &entry_generics),
+        None => entry_generics.clone(),
+    };
+
+    let fields = Fields::Named(FieldsNamed {
+        brace_token: Default::default(),
+        named: fields,
+    });
+    let mut builder = Builder::new(fn_name, None, fields, &generics, vis, &attributes)?;
+    // Calling the function can't fail
+    if let Some(f) = builder
+        .fields
+        .iter()
+        .find(|f| f.validate.is_some() || f.nested.is_some())
+    {
+        return Err(syn::Error::new_spanned(
+            &f.ident,
+            "function builders don't support validate or nested",
+        ));
+    }
+    builder.function = Some(FnTarget {
+        asyncness: sig.asyncness,
+        output,
+        method: impl_block
+            .zip(self_name)
+            .map(|((self_ty, _), self_name)| Method {
+                self_ty: self_ty.clone(),
+                self_name,
+                receiver,
+                entry_generics,
+            }),
+    });
+    Ok(builder)
+}
+
+/// Remove the builder's attributes from the arguments of the function, since
+/// they aren't known to the compiler
+fn strip_attributes(sig: &mut Signature) {
+    for input in sig.inputs.iter_mut() {
+        if let FnArg::Typed(arg) = input {
+            arg.attrs
+                .retain(|a| !a.path().is_ident("builder") && !a.path().is_ident("rename"));
+        }
+    }
+}
+
+/// Get the name of the type an impl block is for
+fn type_name(ty: &Type) -> syn::Result<Ident> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            Ok(path.path.segments.last().unwrap().ident.clone())
+        }
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            "builder is only available for impl blocks of named types",
+        )),
+    }
+}
+
+/// Merge the generics of an impl block with the ones of a function, keeping
+/// the lifetimes first
+fn merge_generics(impl_generics: &Generics, fn_generics: &Generics) -> Generics {
+    let mut generics = impl_generics.clone();
+    let mut params = std::mem::take(&mut generics.params)
+        .into_iter()
+        .collect::<Vec<_>>();
+    params.extend(fn_generics.params.iter().cloned());
+    params.sort_by_key(|p| !matches!(p, GenericParam::Lifetime(_)));
+    generics.params = params.into_iter().collect();
+
+    if let Some(fn_where) = &fn_generics.where_clause {
+        let predicates = fn_where.predicates.iter().cloned();
+        generics.make_where_clause().predicates.extend(predicates);
+    }
+    generics
+}
+
+/// Replaces `Self` with the type of the impl block
+struct ReplaceSelf<'a>(&'a Type);
+
+impl VisitMut for ReplaceSelf<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        match ty {
+            Type::Path(path) if path.qself.is_none() && path.path.is_ident("Self") => {
+                *ty = self.0.clone();
+            }
+            _ => visit_mut::visit_type_mut(self, ty),
+        }
+    }
+}
+
+/// Names the lifetimes elided in the types of a function's arguments, which
+/// the builder storing the arguments has to declare
+#[derive(Default)]
+struct ElidedLifetimes {
+    /// The lifetimes named in place of the elided ones
+    named: Vec<Lifetime>,
+    /// Every lifetime of the arguments' types, named or elided
+    inputs: Vec<Lifetime>,
+    /// The lifetime of the method's receiver, if it's a reference
+    receiver: Option<Lifetime>,
+    /// The lifetime of the return type's elided lifetimes
+    output: Option<Lifetime>,
+    /// The first `impl Trait` found in the arguments' types
+    impl_trait: Option<TypeImplTrait>,
+}
+
+impl ElidedLifetimes {
+    /// Name the elided lifetimes of an argument's type
+    fn name_inputs(&mut self, ty: &Type) -> syn::Result<Type> {
+        let mut ty = ty.clone();
+        self.visit_type_mut(&mut ty);
+        match self.impl_trait.take() {
+            Some(impl_trait) => Err(syn::Error::new_spanned(
+                impl_trait,
+                "impl Trait is not available for the arguments of function builders, use a generic parameter instead",
+            )),
+            None => Ok(ty),
+        }
+    }
+
+    /// Name the elided lifetimes of the return type after the receiver's
+    /// lifetime, or the arguments' lifetime if there is only one
+    fn name_output(&mut self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        self.output = match self.inputs.as_slice() {
+            [lifetime] => self.receiver.clone().or(Some(lifetime.clone())),
+            _ => self.receiver.clone(),
+        };
+        if self.output.is_some() {
+            self.visit_type_mut(&mut ty);
+        }
+        ty
+    }
+
+    /// Get the lifetime replacing an elided one
+    fn next(&mut self) -> Lifetime {
+        if let Some(output) = &self.output {
+            return output.clone();
+        }
+        let lifetime = Lifetime::new(
+            &format!("'__arg{}", self.named.len()),
+            proc_macro2::Span::call_site(),
+        );
+        self.named.push(lifetime.clone());
+        lifetime
+    }
+}
+
+impl VisitMut for ElidedLifetimes {
+    fn visit_type_reference_mut(&mut self, reference: &mut TypeReference) {
+        if reference.lifetime.is_none() {
+            reference.lifetime = Some(self.next());
+        }
+        visit_mut::visit_type_reference_mut(self, reference);
+    }
+
+    fn visit_lifetime_mut(&mut self, lifetime: &mut Lifetime) {
+        if lifetime.ident == "_" {
+            *lifetime = self.next();
+        }
+        if self.output.is_none() {
+            self.inputs.push(lifetime.clone());
+        }
+    }
+
+    fn visit_type_impl_trait_mut(&mut self, impl_trait: &mut TypeImplTrait) {
+        self.impl_trait.get_or_insert_with(|| impl_trait.clone());
+    }
+
+    // Function pointers and `Fn` traits elide their own lifetimes
+    fn visit_type_bare_fn_mut(&mut self, _: &mut TypeBareFn) {}
+
+    fn visit_parenthesized_generic_arguments_mut(&mut self, _: &mut ParenthesizedGenericArguments) {
+    }
+}
@@ -0,0 +1,174 @@
+use crate::attributes::GroupConstraint;
+use crate::field::{to_upper_camel_case, BuilderField};
+use quote::{format_ident, quote};
+use syn::Ident;
+
+/// Maximum number of fields of a group checked at compile time, whose trait
+/// is implemented for up to `2^n` combinations of states
+pub(crate) const MAX_CHECKED_GROUP_SIZE: usize = 8;
+
+/// A group of fields, declared with `#[builder(group = "name")]` on each of
+/// its fields, whose constraint is checked at compile time by the build
+/// function's bounds
+pub(crate) struct FieldGroup {
+    /// The group's name
+    pub(crate) name: Ident,
+    /// The constraint on the fields of the group
+    pub(crate) constraint: GroupConstraint,
+    /// The state type parameters of the fields of the group
+    pub(crate) members: Vec<Ident>,
+    /// The identifiers of the builder's fields of the group
+    pub(crate) fields: Vec<Ident>,
+}
+
+impl FieldGroup {
+    /// Collect the groups declared on the struct's fields, which can be left
+    /// unset. A group's constraint can be set on any of its fields, but must
+    /// be set once at least and can't be contradicted.
+    pub(crate) fn collect(fields: &[BuilderField]) -> syn::Result<Vec<Self>> {
+        let mut groups: Vec<FieldGroup> = Vec::new();
+        let mut constraints: Vec<Option<GroupConstraint>> = Vec::new();
+
+        for field in fields {
+            let Some(name) = &field.group else {
+                continue;
+            };
+            // A required field is always set, whatever its group
+            if field.is_required() {
+                return Err(syn::Error::new_spanned(
+                    &field.ty,
+                    "group fields must be `Option<T>` or have a default",
+                ));
+            }
+
+            let index = match groups.iter().position(|g| &g.name == name) {
+                Some(index) => index,
+                None => {
+                    groups.push(FieldGroup {
+                        name: name.clone(),
+                        constraint: GroupConstraint::Exclusive,
+                        members: Vec::new(),
+                        fields: Vec::new(),
+                    });
+                    constraints.push(None);
+                    groups.len() - 1
+                }
+            };
+            groups[index].members.push(field.state_param());
+            groups[index].fields.push(field.ident.clone());
+
+            match (constraints[index], field.group_constraint) {
+                (Some(current), Some(constraint)) if current != constraint => {
+                    return Err(syn::Error::new_spanned(
+                        name,
+                        format!("conflicting constraints for group {}", name),
+                    ));
+                }
+                (_, Some(constraint)) => constraints[index] = Some(constraint),
+                (_, None) => {}
+            }
+        }
+
+        groups
+            .into_iter()
+            .zip(constraints)
+            .map(|(mut group, constraint)| {
+                group.constraint = constraint.ok_or_else(|| {
+                    syn::Error::new_spanned(
+                        &group.name,
+                        format!(
+                            "group {} needs either an exclusive or an at_least_one constraint",
+                            group.name
+                        ),
+                    )
+                })?;
+                Ok(group)
+            })
+            .collect()
+    }
+
+    /// Generate the identifier of the trait implemented by the valid states
+    /// of the group's fields
+    pub(crate) fn trait_ident(&self, builder_name: &Ident) -> Ident {
+        let name = to_upper_camel_case(&self.name.to_string());
+        format_ident!("{}{}Group", builder_name, name)
+    }
+
+    /// Generate the error variant identifier for the group's constraint being
+    /// violated, checked at runtime by mutable builders
+    pub(crate) fn error_variant(&self) -> Ident {
+        let name = to_upper_camel_case(&self.name.to_string());
+        format_ident!("{}Group", name)
+    }
+
+    /// Get the message describing the group's constraint
+    pub(crate) fn message(&self, builder_name: &Ident) -> String {
+        match self.constraint {
+            GroupConstraint::Exclusive => format!(
+                "at most one field of the `{}` group of `{}` can be set",
+                self.name, builder_name
+            ),
+            GroupConstraint::AtLeastOne => format!(
+                "at least one field of the `{}` group of `{}` must be set",
+                self.name, builder_name
+            ),
+        }
+    }
+
+    /// Generate the runtime check of the group's constraint, returning the
+    /// provided error if the constraint is violated
+    pub(crate) fn runtime_check(
+        &self,
+        error: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let fields = &self.fields;
+        let violated = match self.constraint {
+            GroupConstraint::Exclusive => quote!(set > 1),
+            GroupConstraint::AtLeastOne => quote!(set == 0),
+        };
+
+        quote!({
+            let set = [#(self.#fields.is_some()),*].into_iter().filter(|s| *s).count();
+            if #violated {
+                return Err(#error);
+            }
+        })
+    }
+
+    /// Generate the trait implemented by the tuples of the valid states of
+    /// the group's fields
+    pub(crate) fn constraint_trait(
+        &self,
+        builder_name: &Ident,
+        set_marker: &Ident,
+        unset_marker: &Ident,
+    ) -> proc_macro2::TokenStream {
+        let trait_ident = self.trait_ident(builder_name);
+        let count = self.members.len();
+        let message = self.message(builder_name);
+
+        // Enumerate every combination of states, keeping the valid ones
+        let valid_states = (0..1u32 << count)
+            .filter(|states| match self.constraint {
+                GroupConstraint::Exclusive => states.count_ones() <= 1,
+                GroupConstraint::AtLeastOne => states.count_ones() >= 1,
+            })
+            .map(|states| {
+                let markers = (0..count).map(|i| {
+                    if states & (1 << i) != 0 {
+                        set_marker
+                    } else {
+                        unset_marker
+                    }
+                });
+                quote!(impl #trait_ident for (#(#markers,)*) {})
+            });
+
+        quote!(
+            #[diagnostic::on_unimplemented(message = #message)]
+            pub trait #trait_ident {}
+
+            #(#valid_states)*
+        )
+    }
+}
@@ -1,226 +1,1707 @@
+mod attributes;
+mod field;
+mod function;
+mod group;
+
+use crate::attributes::{Conversion, GroupConstraint, Pattern, StructAttributes};
+use crate::field::{BuilderField, EachSetter, FieldKind};
+use crate::function::FnTarget;
+use crate::group::{FieldGroup, MAX_CHECKED_GROUP_SIZE};
 use proc_macro::TokenStream;
-use proc_macro_error::emit_error;
+use proc_macro_error::proc_macro_error;
 use quote::{format_ident, quote};
-use syn::spanned::Spanned;
 use syn::{
-    parse_macro_input, Attribute, DeriveInput, Expr, ExprLit, Field, Fields, Ident, Lit,
-    MetaNameValue,
+    parse_macro_input, Data, DeriveInput, Fields, GenericParam, Generics, Ident, Item, Type,
+    Visibility,
 };
 
-#[proc_macro_derive(Builder, attributes(rename, builder_defaults))]
-pub fn builder(input: TokenStream) -> TokenStream {
+#[proc_macro_error]
+#[proc_macro_derive(Builder, attributes(rename, builder_defaults, builder))]
+pub fn derive_builder(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
-    // Check if the `builder_defaults` macro is active
-    let use_defaults = ast
-        .attrs
-        .iter()
-        .any(|a| a.path().is_ident("builder_defaults"));
-
-    // Construct the builder struct from the ast
-    let builder = Builder::try_from(ast).expect("failed to create builder");
+    // Construct the builders from the ast: one for a struct, one per variant
+    // for an enum
+    let builders = match builders(ast) {
+        Ok(builders) => builders,
+        Err(err) => return err.to_compile_error().into(),
+    };
 
-    // Fetch the setters, fields and build function for the builder.
-    let setters = builder.setters();
-    let marker_types = builder.marker_types();
-    let builder_fields = builder.fields();
-    let build_fn = builder.build_fn(use_defaults);
-
-    // Get the builder name
-    let builder_name = &builder.builder_name;
+    builders
+        .iter()
+        .map(Builder::expand)
+        .collect::<proc_macro2::TokenStream>()
+        .into()
+}
 
-    quote!(
-        #[derive(Default)]
-        pub struct #builder_name<T = Init> {
-            _marker: std::marker::PhantomData<T>,
-            #(#builder_fields),*
+/// Generate a builder for the arguments of a function: `{fn}_builder()`
+/// returns the builder, its setters are named after the arguments and
+/// `call()` calls the function once every required argument is set.
+///
+/// Arguments take the same `#[builder(...)]` attributes as the fields of a
+/// derived builder. Import the attribute through its path, since the name
+/// clashes with the derive's helper attribute.
+///
+/// The builder stores the arguments, so it declares the lifetimes they
+/// borrow for. Elided references and `'_` are named, but a lifetime hidden
+/// in a path can't be detected: write `Cow<'_, str>` rather than `Cow<str>`.
+///
+/// On an impl block, the attribute generates a builder for each method or
+/// associated function marked with `#[builder(...)]`, returned by
+/// `{fn}_builder()` taking the method's receiver if it has one.
+#[proc_macro_error]
+#[proc_macro_attribute]
+pub fn builder(args: TokenStream, input: TokenStream) -> TokenStream {
+    let expanded = match parse_macro_input!(input as Item) {
+        Item::Fn(function) => {
+            let mut attributes = StructAttributes::default();
+            let parser = syn::meta::parser(|meta| attributes.parse_meta(meta));
+            parse_macro_input!(args with parser);
+            function::expand(attributes, function)
         }
+        Item::Impl(_) if !args.is_empty() => Err(syn::Error::new_spanned(
+            proc_macro2::TokenStream::from(args),
+            "the builders of methods are configured on the methods",
+        )),
+        Item::Impl(item_impl) => function::expand_impl(item_impl),
+        item => Err(syn::Error::new_spanned(
+            item,
+            "builder is only available for functions and impl blocks",
+        )),
+    };
 
-        #[derive(Default)]
-        pub struct Final;
-        #[derive(Default)]
-        pub struct Init;
-        #(#marker_types)*
+    match expanded {
+        Ok(expanded) => expanded.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
 
-        #(#setters)*
+/// Construct the builders for the derive input
+fn builders(input: DeriveInput) -> syn::Result<Vec<Builder>> {
+    let attributes = StructAttributes::parse(&input.attrs)?;
+    if attributes.constant && attributes.use_defaults {
+        return Err(syn::Error::new(
+            attributes.key_span("const"),
+            "const builders can't be combined with builder_defaults",
+        ));
+    }
 
-        impl #builder_name<Final> {
-            #build_fn
-        }
-    )
-    .into()
+    match input.data {
+        Data::Struct(data) => Ok(vec![Builder::new(
+            &input.ident,
+            None,
+            data.fields,
+            &input.generics,
+            &input.vis,
+            &attributes,
+        )?]),
+        Data::Enum(_) if attributes.name.is_some() => Err(syn::Error::new_spanned(
+            input.ident,
+            "name is not available for enums, their builders are named after their variants",
+        )),
+        Data::Enum(data) => data
+            .variants
+            .into_iter()
+            .map(|v| {
+                Builder::new(
+                    &input.ident,
+                    Some(v.ident),
+                    v.fields,
+                    &input.generics,
+                    &input.vis,
+                    &attributes,
+                )
+            })
+            .collect(),
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            input.ident,
+            "builder only available for Struct and Enum",
+        )),
+    }
 }
 
 struct Builder {
-    fields: Vec<Field>,
+    fields: Vec<BuilderField>,
+    groups: Vec<FieldGroup>,
+    style: FieldsStyle,
     struct_name: Ident,
+    variant: Option<Ident>,
     builder_name: Ident,
+    generics: Generics,
+    vis: Visibility,
+    builder_vis: Visibility,
+    setter_prefix: String,
+    build_fn: Ident,
+    /// Names of the builder's other methods, which the fields' methods can't
+    /// take
+    reserved: Vec<String>,
+    validate: Option<syn::Path>,
+    pattern: Pattern,
+    constant: bool,
+    serde: bool,
+    use_defaults: bool,
+    function: Option<FnTarget>,
+}
+
+/// How the fields of the built struct or variant are declared
+enum FieldsStyle {
+    Named,
+    Unnamed,
+    Unit,
 }
 
-impl TryFrom<DeriveInput> for Builder {
-    type Error = Box<dyn std::error::Error>;
+impl Builder {
+    /// Create the builder for a struct, or for one of the variants of an enum
+    fn new(
+        struct_name: &Ident,
+        variant: Option<Ident>,
+        fields: Fields,
+        generics: &Generics,
+        vis: &Visibility,
+        attributes: &StructAttributes,
+    ) -> syn::Result<Self> {
+        let builder_name = match (&variant, &attributes.name) {
+            (_, Some(name)) => name.clone(),
+            (Some(variant), None) => format_ident!("{}{}Builder", struct_name, variant),
+            (None, None) => format_ident!("{}Builder", struct_name),
+        };
+        let setter_prefix = attributes.prefix.clone().unwrap_or("with_".to_string());
+        let style = match fields {
+            Fields::Named(_) => FieldsStyle::Named,
+            Fields::Unnamed(_) => FieldsStyle::Unnamed,
+            Fields::Unit => FieldsStyle::Unit,
+        };
+
+        // Fields without their own conversion use the struct's one
+        let fields = fields
+            .into_iter()
+            .enumerate()
+            .map(|(index, f)| {
+                let mut field = BuilderField::new(index, f)?;
+                field.conversion = field.conversion.or(attributes.conversion);
+                Ok(field)
+            })
+            .collect::<syn::Result<Vec<_>>>()?;
+        let groups = FieldGroup::collect(&fields)?;
+
+        // Every valid combination of states of a group checked at compile
+        // time gets an implementation of the group's trait
+        if let Some(group) = groups.iter().find(|g| {
+            attributes.pattern == Pattern::Owned
+                && !attributes.serde
+                && g.members.len() > MAX_CHECKED_GROUP_SIZE
+        }) {
+            return Err(syn::Error::new_spanned(
+                &group.name,
+                format!(
+                    "group {} has more than {} fields, use the mutable pattern to check it at runtime",
+                    group.name, MAX_CHECKED_GROUP_SIZE
+                ),
+            ));
+        }
+
+        // Without a prefix, the setters of unnamed fields would be named
+        // after their index
+        if let Some(f) = fields.iter().find(|f| {
+            setter_prefix.is_empty() && f.setter_name.starts_with(|c: char| c.is_ascii_digit())
+        }) {
+            return Err(syn::Error::new_spanned(
+                &f.ty,
+                "unnamed fields need a setter prefix",
+            ));
+        }
+        let build_fn = attributes
+            .build_fn
+            .clone()
+            .unwrap_or(format_ident!("build"));
+        let reserved = reserved_names(&build_fn, attributes);
+        check_setter_names(&fields, &setter_prefix, attributes.pattern, &reserved)?;
+        check_error_variants(&fields, &groups, attributes)?;
+
+        if attributes.constant {
+            check_const(&fields, attributes)?;
+        }
+        // The result of a nested builder can't be deserialized
+        if let Some(f) = fields
+            .iter()
+            .find(|f| attributes.serde && f.nested.is_some())
+        {
+            return Err(syn::Error::new_spanned(
+                &f.ty,
+                "serde builders don't support nested",
+            ));
+        }
+
+        Ok(Builder {
+            fields,
+            groups,
+            style,
+            struct_name: struct_name.clone(),
+            variant,
+            builder_name,
+            generics: generics.clone(),
+            vis: vis.clone(),
+            builder_vis: attributes.vis.clone().unwrap_or(vis.clone()),
+            setter_prefix,
+            build_fn,
+            reserved,
+            validate: attributes.validate.clone(),
+            pattern: attributes.pattern,
+            constant: attributes.constant,
+            serde: attributes.serde,
+            use_defaults: attributes.use_defaults,
+            function: None,
+        })
+    }
+
+    /// Generate the builder struct and all its implementations
+    fn expand(&self) -> proc_macro2::TokenStream {
+        // Fetch the setters, fields and build function for the builder.
+        let setters = self.setters();
+        let builder_fields = self.fields();
+        let build_fn = self.build_fn();
+        let error_enum = self.function.is_none().then(|| self.error_enum());
+        let entry = self.entry();
+
+        // Get the builder name and the generics to carry over from the struct
+        let builder_name = &self.builder_name;
+        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let struct_generics = self.struct_generics();
+        let phantom_types = self.phantom_types();
+        let struct_name = &self.struct_name;
+        let (ready, error_name) = (self.ready_trait(), self.error_name());
+        let (vis, builder_vis) = (&self.vis, &self.builder_vis);
+        let build_fn_name = &self.build_fn;
+        let (set_marker, unset_marker) = (self.set_marker(), self.unset_marker());
+        let unset_args = self.state_args(|_| quote!(#unset_marker));
+        let build_generics = self.build_generics();
+        let (build_impl_generics, _, build_where_clause) = build_generics.split_for_impl();
+        let build_args = self.state_args(|f| {
+            if f.is_required() && self.requires_set_state() {
+                quote!(#set_marker)
+            } else {
+                let state = f.state_param();
+                quote!(#state)
+            }
+        });
+        let group_traits = self
+            .groups
+            .iter()
+            .filter(|_| !self.runtime_checked())
+            .map(|g| g.constraint_trait(builder_name, &set_marker, &unset_marker));
+        let build_arg = match self.pattern {
+            Pattern::Owned => quote!(self),
+            Pattern::Mutable => quote!(&self),
+        };
+        let default_fields = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            quote!(#(#cfgs)* #name: None)
+        });
+        let doc = self.doc();
+
+        // `Default::default()` isn't const, const builders get their own
+        // constructor
+        let const_new = self.constant.then(|| {
+            let default_fields = self.set_fields().map(|f| {
+                let (name, cfgs) = (&f.ident, &f.cfgs);
+                quote!(#(#cfgs)* #name: None)
+            });
+            quote!(
+                impl #impl_generics #builder_name<#(#unset_args),*> #where_clause {
+                    pub const fn new() -> Self {
+                        Self {
+                            _marker: std::marker::PhantomData,
+                            #(#default_fields),*
+                        }
+                    }
+                }
+            )
+        });
+        let built = match self.constant || !self.fallible() {
+            true => quote!(Ok(#builder_name::#build_fn_name(#build_arg))),
+            false => quote!(#builder_name::#build_fn_name(#build_arg)),
+        };
+        // Function builders call their function rather than building a value
+        // for another builder
+        let ready = self.function.is_none().then(|| {
+            quote!(
+                // The trait exposes the built struct, so it can't be more
+                // visible than it. Its method isn't named `build` so it doesn't
+                // shadow the by-reference build function of mutable builders.
+                #vis trait #ready {
+                    type Output;
+
+                    fn finish(self) -> Result<Self::Output, #error_name>;
+                }
+
+                impl #build_impl_generics #ready for #builder_name<#(#build_args),*> #build_where_clause {
+                    type Output = #struct_name #ty_generics;
+
+                    fn finish(self) -> Result<Self::Output, #error_name> {
+                        #built
+                    }
+                }
+            )
+        });
 
-    fn try_from(input: DeriveInput) -> Result<Self, Self::Error> {
-        let struct_name = input.ident.clone();
-        let builder_name = format_ident!("{}Builder", struct_name);
+        let (derive_serde, skip_marker) = match self.serde {
+            true => (
+                Some(quote!(#[derive(serde::Deserialize)])),
+                Some(quote!(#[serde(skip)])),
+            ),
+            false => (None, None),
+        };
+        let merge = self.serde.then(|| self.merge());
+        let clone_and_debug = self.clone_and_debug();
+        // The builder of a method can only be created from its receiver
+        let receiver_field = self.receiver().map(|ty| quote!(__receiver: #ty,));
+        let default = receiver_field.is_none().then(|| {
+            quote!(
+                impl #impl_generics Default for #builder_name<#(#unset_args),*> #where_clause {
+                    fn default() -> Self {
+                        Self {
+                            _marker: std::marker::PhantomData,
+                            #(#default_fields),*
+                        }
+                    }
+                }
+            )
+        });
 
-        match input.data {
-            syn::Data::Struct(syn::DataStruct {
-                fields: Fields::Named(syn::FieldsNamed { named, .. }),
-                ..
-            }) => Ok(Builder {
-                fields: named.into_iter().collect(),
-                struct_name,
-                builder_name,
-            }),
-            _ => {
-                emit_error!(input.ident, "builder only available for Struct");
-                Err("".into())
+        quote!(
+            #[doc = #doc]
+            #derive_serde
+            #builder_vis struct #builder_name #struct_generics #where_clause {
+                #skip_marker
+                _marker: std::marker::PhantomData<(#(#phantom_types,)*)>,
+                #receiver_field
+                #(#builder_fields),*
             }
+
+            #default
+
+            #const_new
+            #merge
+            #clone_and_debug
+
+            pub struct #set_marker;
+            pub struct #unset_marker;
+            #(#group_traits)*
+
+            #setters
+
+            impl #build_impl_generics #builder_name<#(#build_args),*> #build_where_clause {
+                #build_fn
+            }
+
+            #ready
+
+            #error_enum
+
+            #entry
+        )
+    }
+
+    /// Generate the generics of the builder struct declaration: the struct's
+    /// own generics followed by one state parameter per field, defaulting to
+    /// the unset marker.
+    fn struct_generics(&self) -> Generics {
+        let unset_marker = self.unset_marker();
+        let mut generics = self.generics.clone();
+        generics.params.extend(
+            self.state_params()
+                .into_iter()
+                .map(|p| GenericParam::Type(syn::parse_quote!(#p = #unset_marker))),
+        );
+        generics
+    }
+
+    /// Generate the generics of the setters' impl block: the struct's own
+    /// generics followed by one unbounded state parameter per field.
+    fn setters_generics(&self) -> Generics {
+        let mut generics = self.generics.clone();
+        generics.params.extend(
+            self.state_params()
+                .into_iter()
+                .map(|p| GenericParam::Type(syn::parse_quote!(#p))),
+        );
+        generics
+    }
+
+    /// Generate the generics of the build function's impl block: the
+    /// struct's own generics followed by the state parameters of the fields
+    /// belonging to a group, bound by the group's constraint.
+    ///
+    /// Builders checked at runtime and builders falling back to defaults can
+    /// be built whatever their state.
+    fn build_generics(&self) -> Generics {
+        let mut generics = self.generics.clone();
+        generics.params.extend(
+            self.fields
+                .iter()
+                .filter(|f| (!f.is_required() || !self.requires_set_state()) && self.has_state(f))
+                .map(|f| {
+                    let state = f.state_param();
+                    GenericParam::Type(syn::parse_quote!(#state))
+                }),
+        );
+
+        let where_clause = generics.make_where_clause();
+        for group in self.groups.iter().filter(|_| !self.runtime_checked()) {
+            let trait_ident = group.trait_ident(&self.builder_name);
+            let members = &group.members;
+            where_clause
+                .predicates
+                .push(syn::parse_quote!((#(#members,)*): #trait_ident));
         }
+        generics
     }
-}
 
-impl Builder {
-    /// Generate the fields for the builder struct
-    fn fields(&self) -> Vec<proc_macro2::TokenStream> {
-        self.fields
+    /// Generate the generic arguments of the struct, without their bounds
+    fn generic_args(&self) -> Vec<proc_macro2::TokenStream> {
+        self.generics
+            .params
             .iter()
-            .map(|f| {
-                let name = &f.ident;
-                let ty = &f.ty;
-                quote!(#name: Option<#ty>)
+            .map(|p| match p {
+                GenericParam::Lifetime(l) => {
+                    let lifetime = &l.lifetime;
+                    quote!(#lifetime)
+                }
+                GenericParam::Type(t) => {
+                    let ident = &t.ident;
+                    quote!(#ident)
+                }
+                GenericParam::Const(c) => {
+                    let ident = &c.ident;
+                    quote!(#ident)
+                }
             })
             .collect()
     }
 
-    /// Generate the marker type identifier for the builder's field
-    fn marker_type_ident(&self, field: String) -> Ident {
-        format_ident!("{}Of{}", self.builder_name, field)
+    /// Generate the types held by the builder's marker: the struct's own
+    /// generics, which some variants of an enum may not use, followed by the
+    /// state parameters
+    fn phantom_types(&self) -> Vec<proc_macro2::TokenStream> {
+        let generics = self.generics.params.iter().filter_map(|p| match p {
+            GenericParam::Lifetime(l) => {
+                let lifetime = &l.lifetime;
+                Some(quote!(&#lifetime ()))
+            }
+            GenericParam::Type(t) => {
+                let ident = &t.ident;
+                Some(quote!(fn() -> #ident))
+            }
+            GenericParam::Const(_) => None,
+        });
+        let states = self.state_params().into_iter().map(|p| quote!(#p));
+        generics.chain(states).collect()
+    }
+
+    /// Generate the generic arguments of the builder with the provided state
+    /// for every field with a state
+    fn state_args(
+        &self,
+        state: impl Fn(&BuilderField) -> proc_macro2::TokenStream,
+    ) -> Vec<proc_macro2::TokenStream> {
+        let states = self.fields.iter().filter(|f| self.has_state(f)).map(state);
+        self.generic_args().into_iter().chain(states).collect()
+    }
+
+    /// Generate the identifier of the error returned by the build function
+    fn error_name(&self) -> Ident {
+        format_ident!("{}Error", self.builder_name)
     }
 
-    /// Generate the marker types for the builder struct
-    fn marker_types(&self) -> Vec<proc_macro2::TokenStream> {
+    /// Generate the identifier of the trait implemented by the builder once
+    /// it can be built, used to drive it from another builder
+    fn ready_trait(&self) -> Ident {
+        format_ident!("{}Ready", self.builder_name)
+    }
+
+    /// Generate the marker type of a field that has been set. Markers are
+    /// prefixed with the builder name so that several builders can live in
+    /// the same module.
+    fn set_marker(&self) -> Ident {
+        format_ident!("{}Set", self.builder_name)
+    }
+
+    /// Generate the marker type of a field that has not been set yet
+    fn unset_marker(&self) -> Ident {
+        format_ident!("{}Unset", self.builder_name)
+    }
+
+    /// Generate the state type parameters for the builder struct, one per
+    /// required or grouped field
+    fn state_params(&self) -> Vec<Ident> {
         self.fields
             .iter()
-            .map(|f| self.marker_type_ident(maybe_ident_to_string(&f.ident)))
-            .map(|ty| quote! {pub struct #ty;})
+            .filter(|f| self.has_state(f))
+            .map(BuilderField::state_param)
             .collect()
     }
 
-    /// Generate the setters for the builder struct
-    fn setters(&self) -> Vec<proc_macro2::TokenStream> {
-        let mut fields = self.fields.iter().peekable();
+    /// Get the fields that can be set on the builder, leaving out the skipped
+    /// ones
+    fn set_fields(&self) -> impl Iterator<Item = &BuilderField> {
+        self.fields.iter().filter(|f| !f.is_skipped())
+    }
+
+    /// Get the type of the receiver stored by the builder of a method
+    fn receiver(&self) -> Option<&Type> {
+        self.function.as_ref()?.method.as_ref()?.receiver.as_ref()
+    }
+
+    /// Whether the builder's fields are checked when building rather than
+    /// tracked in its type: mutable builders and deserialized builders can't
+    /// know which fields are set
+    fn runtime_checked(&self) -> bool {
+        self.pattern == Pattern::Mutable || self.serde
+    }
+
+    /// Whether the build function is only available once every required
+    /// field is set
+    fn requires_set_state(&self) -> bool {
+        !self.runtime_checked() && !self.use_defaults
+    }
+
+    /// Whether the build function can fail. Without validators, nested
+    /// builders or groups checked at runtime, a builder falling back to
+    /// defaults always builds its struct.
+    fn fallible(&self) -> bool {
+        !self.use_defaults
+            || self.validate.is_some()
+            || self
+                .fields
+                .iter()
+                .any(|f| f.validate.is_some() || f.nested.is_some())
+            || (self.runtime_checked() && !self.groups.is_empty())
+    }
+
+    /// Whether the field has a state type parameter in the builder. Mutable
+    /// builders don't track their state in their type.
+    fn has_state(&self, f: &BuilderField) -> bool {
+        self.pattern == Pattern::Owned && f.has_state()
+    }
+
+    /// Generate the `Clone` and `Debug` implementations of the builder, for
+    /// any state. Each is bound on the types stored in the builder rather
+    /// than on the struct's generics, so a field that can't be cloned or
+    /// formatted only disables the matching implementation. The bounds are
+    /// higher-ranked so that they aren't rejected when they don't hold for a
+    /// concrete type.
+    ///
+    /// `Debug` shows the fields that are set, with the values of secret
+    /// fields replaced by `***`.
+    fn clone_and_debug(&self) -> proc_macro2::TokenStream {
         let builder_name = &self.builder_name;
-        let mut marker_type_prev = Ident::new("Init", proc_macro2::Span::call_site());
+        let generics = self.setters_generics();
+        let generic_args = self.generic_args();
+        let state_params = self.state_params();
+        let bounded = |trait_name: proc_macro2::TokenStream, secret: bool| {
+            let mut generics = generics.clone();
+            let where_clause = generics.make_where_clause();
+            for f in self
+                .set_fields()
+                .filter(|f| f.cfgs.is_empty() && (!f.secret || !secret))
+            {
+                let storage = f.storage_ty();
+                where_clause
+                    .predicates
+                    .push(syn::parse_quote!(for<'__builder> #storage: #trait_name));
+            }
+            // `Debug` leaves the receiver of a method out
+            if let Some(receiver) = self.receiver().filter(|_| !secret) {
+                where_clause
+                    .predicates
+                    .push(syn::parse_quote!(for<'__builder> #receiver: #trait_name));
+            }
+            generics
+        };
 
-        let mut setters = Vec::with_capacity(self.fields.len());
+        let clone_generics = bounded(quote!(Clone), false);
+        let (impl_generics, _, where_clause) = clone_generics.split_for_impl();
+        let cloned = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            quote!(#(#cfgs)* #name: self.#name.clone())
+        });
+        let receiver = self
+            .receiver()
+            .map(|_| quote!(__receiver: self.__receiver.clone(),));
+        let clone = quote!(
+            impl #impl_generics Clone for #builder_name<#(#generic_args,)* #(#state_params),*> #where_clause {
+                fn clone(&self) -> Self {
+                    Self {
+                        _marker: std::marker::PhantomData,
+                        #receiver
+                        #(#cloned),*
+                    }
+                }
+            }
+        );
 
-        // Generate all the fields in order to rebuild the builder when
-        // modifying the marker
-        let builder_fields = fields.clone().map(|f| {
-            let name = &f.ident;
-            quote!(#name: self.#name)
+        let debug_generics = bounded(quote!(std::fmt::Debug), true);
+        let (impl_generics, _, where_clause) = debug_generics.split_for_impl();
+        let debug_name = builder_name.to_string();
+        let debugged = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            let display_name = f.display_name();
+            let value = match f.secret {
+                true => quote!(&self.#name.as_ref().map(|_| format_args!("***"))),
+                false => quote!(&self.#name),
+            };
+            quote!(#(#cfgs)* debug.field(#display_name, #value);)
         });
+        let debug = quote!(
+            impl #impl_generics std::fmt::Debug for #builder_name<#(#generic_args,)* #(#state_params),*> #where_clause {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    let mut debug = f.debug_struct(#debug_name);
+                    #(#debugged)*
+                    debug.finish()
+                }
+            }
+        );
 
-        while let Some(f) = fields.next() {
-            let fn_name = format_ident!("with_{}", get_field_name(f));
-            let ty = &f.ty;
-            let name = &f.ident;
-            let marker_type_out = self.marker_type_ident(maybe_ident_to_string(name));
+        quote!(
+            #clone
+            #debug
+        )
+    }
 
-            // Handle the case of the last field separately.
-            let (marker_in, marker_out) = if fields.peek().is_none() {
-                (quote!(<#marker_type_prev>), quote!(<Final>))
-            } else {
-                (quote!(<#marker_type_prev>), quote!(<#marker_type_out>))
-            };
+    /// Generate the function overlaying another builder onto the builder:
+    /// the fields set on the other builder replace the builder's ones. The
+    /// other builder can be in any state, since the fields are checked when
+    /// building.
+    fn merge(&self) -> proc_macro2::TokenStream {
+        let builder_name = &self.builder_name;
+        let setters_generics = self.setters_generics();
+        let (impl_generics, _, where_clause) = setters_generics.split_for_impl();
+        let generic_args = self.generic_args();
+        let state_params = self.state_params();
+        let other_params = state_params
+            .iter()
+            .map(|p| format_ident!("{}Other", p))
+            .collect::<Vec<_>>();
+        let fields = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            quote!(#(#cfgs)* #name: other.#name.or(self.#name))
+        });
 
-            let builder_fields = builder_fields.clone();
+        quote!(
+            impl #impl_generics #builder_name<#(#generic_args,)* #(#state_params),*> #where_clause {
+                pub fn merge<#(#other_params),*>(
+                    self,
+                    other: #builder_name<#(#generic_args,)* #(#other_params),*>,
+                ) -> Self {
+                    Self {
+                        _marker: std::marker::PhantomData,
+                        #(#fields),*
+                    }
+                }
+            }
+        )
+    }
 
-            let setter = quote!(
-                impl #builder_name #marker_in {
-                    pub fn #fn_name(mut self, #name: #ty) -> #builder_name #marker_out {
+    /// Generate the documentation of the builder struct, listing the fields
+    /// that must be set before building and the ones that can be left unset
+    fn doc(&self) -> String {
+        let struct_name = &self.struct_name;
+        let mut doc = match (&self.variant, &self.function) {
+            (Some(variant), _) => format!(
+                " Builder for the [`{0}::{1}`] variant of [`{0}`].",
+                struct_name, variant
+            ),
+            (
+                None,
+                Some(FnTarget {
+                    method: Some(method),
+                    ..
+                }),
+            ) => format!(
+                " Builder for the arguments of [`{}::{}`].",
+                method.self_name, struct_name
+            ),
+            (None, Some(_)) => format!(" Builder for the arguments of [`{}`].", struct_name),
+            (None, None) => format!(" Builder for [`{}`].", struct_name),
+        };
+        let kind = match self.function {
+            Some(_) => "arguments",
+            None => "fields",
+        };
+
+        let is_required = |f: &BuilderField| f.is_required() && !self.use_defaults;
+        let list = |required: bool| {
+            self.set_fields()
+                .filter(|f| is_required(f) == required)
+                .map(|f| {
+                    format!(
+                        "\n - `{}`: set with `{}{}`",
+                        f.display_name(),
+                        self.setter_prefix,
+                        f.setter_name
+                    )
+                })
+                .collect::<String>()
+        };
+        for (title, fields) in [("Required", list(true)), ("Optional", list(false))] {
+            if !fields.is_empty() {
+                doc.push_str(&format!("\n\n {} {}:{}", title, kind, fields));
+            }
+        }
+        doc
+    }
+
+    /// Generate the fields for the builder struct
+    fn fields(&self) -> Vec<proc_macro2::TokenStream> {
+        self.set_fields()
+            .map(|f| {
+                let (name, cfgs) = (&f.ident, &f.cfgs);
+                let storage = f.storage_ty();
+                let serde = self.serde.then(|| quote!(#[serde(default)]));
+                quote!(#(#cfgs)* #serde #name: #storage)
+            })
+            .collect()
+    }
+
+    /// Generate the setters for the builder struct
+    ///
+    /// Every setter is available whatever the state of the builder. Setters
+    /// of required and grouped fields only move their own field's state to
+    /// set, so they can be called in any order. Setters of other optional and
+    /// defaulted fields leave the state untouched.
+    ///
+    /// Setters of mutable builders take and return the builder by mutable
+    /// reference instead.
+    fn setters(&self) -> proc_macro2::TokenStream {
+        let builder_name = &self.builder_name;
+        let setters_generics = self.setters_generics();
+        let (impl_generics, _, where_clause) = setters_generics.split_for_impl();
+        let generic_args = self.generic_args();
+        let state_params = self.state_params();
+        let set_marker = self.set_marker();
+        let constness = self.constant.then(|| quote!(const));
+        let getters = self.getters();
+        let receiver = self
+            .receiver()
+            .map(|_| quote!(__receiver: self.__receiver,));
+
+        let setters = self.set_fields().map(|f| {
+            let fn_name = format_ident!("{}{}", self.setter_prefix, f.setter_name);
+            let ty = &f.ty;
+            let name = &f.ident;
+
+            let setter = if self.constant {
+                // Const setters rebuild the builder from its fields
+                let state = f.state_param();
+                let states_out = state_params.iter().map(|s| {
+                    if self.has_state(f) && s == &state {
+                        quote!(#set_marker)
+                    } else {
+                        quote!(#s)
+                    }
+                });
+                setter_fn(
+                    f,
+                    &fn_name,
+                    &constness,
+                    quote!(self),
+                    quote!(#builder_name<#(#generic_args,)* #(#states_out),*>),
+                    self.const_rebuild(f, quote!(Some(#name))),
+                )
+            } else if self.pattern == Pattern::Mutable {
+                setter_fn(
+                    f,
+                    &fn_name,
+                    &constness,
+                    quote!(&mut self),
+                    quote!(&mut Self),
+                    quote!({
                         self.#name = Some(#name);
+                        self
+                    }),
+                )
+            } else if self.has_state(f) {
+                // Rebuild the builder, moving this field's state to set
+                let state = f.state_param();
+                let states_out = state_params.iter().map(|s| {
+                    if s == &state {
+                        quote!(#set_marker)
+                    } else {
+                        quote!(#s)
+                    }
+                });
+                let builder_fields = self.set_fields().map(|f| {
+                    let (other, cfgs) = (&f.ident, &f.cfgs);
+                    if other == name {
+                        quote!(#name: Some(#name))
+                    } else {
+                        quote!(#(#cfgs)* #other: self.#other)
+                    }
+                });
+
+                setter_fn(
+                    f,
+                    &fn_name,
+                    &constness,
+                    quote!(self),
+                    quote!(#builder_name<#(#generic_args,)* #(#states_out),*>),
+                    quote!(
                         #builder_name {
                             _marker: std::marker::PhantomData,
+                            #receiver
                             #(#builder_fields),*
                         }
-                    }
+                    ),
+                )
+            } else {
+                setter_fn(
+                    f,
+                    &fn_name,
+                    &constness,
+                    quote!(self),
+                    quote!(Self),
+                    quote!(Self { #name: Some(#name), ..self }),
+                )
+            };
+
+            // The state of a grouped field can't depend on the value given
+            // at runtime, so only ungrouped optional fields can be set from
+            // an `Option`
+            let opt_setter = match f.kind {
+                FieldKind::Optional(_) if !self.has_state(f) => {
+                    let opt_fn_name = format_ident!("{}_opt", fn_name);
+                    let value = match f.nested {
+                        Some(_) => quote!(#name.map(Ok)),
+                        None => quote!(#name),
+                    };
+
+                    let rebuild = self.const_rebuild(f, quote!(#name));
+                    Some(match self.pattern {
+                        Pattern::Owned if self.constant => quote!(
+                            pub const fn #opt_fn_name(self, #name: #ty) -> Self {
+                                #rebuild
+                            }
+                        ),
+                        Pattern::Owned => quote!(
+                            pub #constness fn #opt_fn_name(self, #name: #ty) -> Self {
+                                Self { #name: #value, ..self }
+                            }
+                        ),
+                        Pattern::Mutable => quote!(
+                            pub fn #opt_fn_name(&mut self, #name: #ty) -> &mut Self {
+                                self.#name = #value;
+                                self
+                            }
+                        ),
+                    })
                 }
+                _ => None,
+            };
+            let each_setter = f
+                .each
+                .as_ref()
+                .map(|each| each_setter_fn(f, each, self.pattern));
+
+            // Every setter of the field carries its documentation
+            let attrs = f.docs.iter().chain(&f.cfgs).collect::<Vec<_>>();
+            let opt_setter = opt_setter.map(|s| quote!(#(#attrs)* #s));
+            let each_setter = each_setter.map(|s| quote!(#(#attrs)* #s));
+
+            quote!(
+                #(#attrs)*
+                #setter
+                #opt_setter
+                #each_setter
+            )
+        });
+
+        quote!(
+            impl #impl_generics #builder_name<#(#generic_args,)* #(#state_params),*> #where_clause {
+                #(#setters)*
+                #(#getters)*
+            }
+        )
+    }
+
+    /// Generate the getters of the builder, reading the value of a field if
+    /// it's set, and the queries checking whether a field is set
+    fn getters(&self) -> Vec<proc_macro2::TokenStream> {
+        let constness = self.constant.then(|| quote!(const));
+
+        self.set_fields()
+            .map(|f| {
+                let (name, cfgs) = (&f.ident, &f.cfgs);
+                let (getter, has) = f.getter_names(&self.setter_prefix, &self.reserved);
+                let value_ty = f.value_ty();
+                let value = match f.nested {
+                    Some(_) => quote!(self.#name.as_ref().and_then(|r| r.as_ref().ok())),
+                    None => quote!(self.#name.as_ref()),
+                };
+
+                quote!(
+                    #(#cfgs)*
+                    pub #constness fn #getter(&self) -> Option<&#value_ty> {
+                        #value
+                    }
+
+                    #(#cfgs)*
+                    pub #constness fn #has(&self) -> bool {
+                        self.#name.is_some()
+                    }
+                )
+            })
+            .collect()
+    }
+
+    /// Generate the entry points to the builder. For an enum variant, this
+    /// is a `{variant}_builder()` constructor on the enum.
+    fn entry(&self) -> proc_macro2::TokenStream {
+        // The builder of a method is returned by a method of its type
+        match &self.function {
+            Some(function) if function.method.is_some() => return quote!(),
+            Some(_) => return self.function_entry(),
+            None => {}
+        }
+        let Some(variant) = &self.variant else {
+            let struct_entry = self.struct_entry();
+            let to_builder = self.to_builder();
+            return quote!(
+                #struct_entry
+                #to_builder
             );
+        };
+
+        let (builder_name, vis) = (&self.builder_name, &self.builder_vis);
+        let struct_name = &self.struct_name;
+        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let unset_marker = self.unset_marker();
+        let unset_args = self.state_args(|_| quote!(#unset_marker));
+        let fn_name = format_ident!("{}_builder", to_snake_case(&variant.to_string()));
+        let (constness, builder) = match self.constant {
+            true => (quote!(const), quote!(#builder_name::new())),
+            false => (quote!(), quote!(Default::default())),
+        };
+
+        quote!(
+            impl #impl_generics #struct_name #ty_generics #where_clause {
+                #vis #constness fn #fn_name() -> #builder_name<#(#unset_args),*> {
+                    #builder
+                }
+            }
+        )
+    }
+
+    /// Generate the `builder()` constructor on the struct, and `build_with()`
+    /// building the struct from a closure driving the builder
+    ///
+    ///  Like the build function, `build_with()` returns the struct directly
+    ///  when building can't fail, the error enum then having no variant.
+    fn struct_entry(&self) -> proc_macro2::TokenStream {
+        let (builder_name, vis) = (&self.builder_name, &self.builder_vis);
+        let struct_name = &self.struct_name;
+        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let (ready, error_name) = (self.ready_trait(), self.error_name());
+        let unset_marker = self.unset_marker();
+        let unset_args = self.state_args(|_| quote!(#unset_marker));
+        let (constness, builder) = match self.constant {
+            true => (quote!(const), quote!(#builder_name::new())),
+            false => (quote!(), quote!(Default::default())),
+        };
+        let finish = quote!(#ready::finish(f(Self::builder())));
+        let (output, built) = match self.fallible() {
+            true => (quote!(Result<Self, #error_name>), finish),
+            false => (
+                quote!(Self),
+                quote!(match #finish {
+                    Ok(built) => built,
+                    Err(error) => match error {},
+                }),
+            ),
+        };
+
+        quote!(
+            impl #impl_generics #struct_name #ty_generics #where_clause {
+                #vis #constness fn builder() -> #builder_name<#(#unset_args),*> {
+                    #builder
+                }
+
+                #vis fn build_with<__Built: #ready<Output = Self>>(
+                    f: impl FnOnce(#builder_name<#(#unset_args),*>) -> __Built,
+                ) -> #output {
+                    #built
+                }
+            }
+        )
+    }
+
+    /// Generate the `{fn}_builder()` function returning the builder of a
+    /// function's arguments
+    fn function_entry(&self) -> proc_macro2::TokenStream {
+        let (builder_name, vis) = (&self.builder_name, &self.builder_vis);
+        let (impl_generics, _, where_clause) = self.generics.split_for_impl();
+        let unset_marker = self.unset_marker();
+        let unset_args = self.state_args(|_| quote!(#unset_marker));
+        let fn_name = format_ident!("{}_builder", self.struct_name);
+
+        quote!(
+            #vis fn #fn_name #impl_generics() -> #builder_name<#(#unset_args),*> #where_clause {
+                Default::default()
+            }
+        )
+    }
 
-            setters.push(setter);
-            marker_type_prev = marker_type_out;
+    /// Generate the `{fn}_builder()` method returning the builder of a
+    /// method's arguments, moving the method's receiver into the builder
+    fn method_entry(&self) -> proc_macro2::TokenStream {
+        let Some(method) = self.function.as_ref().and_then(|f| f.method.as_ref()) else {
+            return quote!();
+        };
+        let (builder_name, vis) = (&self.builder_name, &self.builder_vis);
+        let (impl_generics, _, where_clause) = method.entry_generics.split_for_impl();
+        let unset_marker = self.unset_marker();
+        let unset_args = self.state_args(|_| quote!(#unset_marker));
+        let fn_name = format_ident!("{}_builder", self.struct_name);
+        let default_fields = self.set_fields().map(|f| {
+            let name = &f.ident;
+            quote!(#name: None)
+        });
+        let (receiver, receiver_field) = match &method.receiver {
+            Some(ty) => (quote!(self: #ty), quote!(__receiver: self,)),
+            None => (quote!(), quote!()),
+        };
+
+        quote!(
+            #vis fn #fn_name #impl_generics(#receiver) -> #builder_name<#(#unset_args),*> #where_clause {
+                #builder_name {
+                    _marker: std::marker::PhantomData,
+                    #receiver_field
+                    #(#default_fields),*
+                }
+            }
+        )
+    }
+
+    /// Generate the conversion from a struct back to its builder, with every
+    /// field set so that it can be built again right away.
+    ///
+    /// Fields of an at least one group are considered set, a valid state for
+    /// the build function whatever the struct's values. Which field of an
+    /// exclusive group holds a value is only known at runtime, so the
+    /// conversion isn't available when the group is checked by the type
+    /// system.
+    fn to_builder(&self) -> proc_macro2::TokenStream {
+        let exclusive = self
+            .groups
+            .iter()
+            .any(|g| g.constraint == GroupConstraint::Exclusive);
+        if exclusive && !self.runtime_checked() {
+            return quote!();
         }
 
-        setters
+        let (builder_name, vis) = (&self.builder_name, &self.builder_vis);
+        let struct_name = &self.struct_name;
+        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let set_marker = self.set_marker();
+        let set_args = self.state_args(|_| quote!(#set_marker));
+
+        let builder_fields = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            let member = &f.member;
+            let value = match (&f.kind, &f.nested) {
+                (FieldKind::Optional(_), Some(_)) => quote!(value.#member.map(Ok)),
+                (FieldKind::Optional(_), None) => quote!(value.#member),
+                (_, Some(_)) => quote!(Some(Ok(value.#member))),
+                (_, None) => quote!(Some(value.#member)),
+            };
+            quote!(#(#cfgs)* #name: #value)
+        });
+
+        quote!(
+            impl #impl_generics From<#struct_name #ty_generics> for #builder_name<#(#set_args),*> #where_clause {
+                // Deprecated fields are still carried over
+                #[allow(deprecated)]
+                fn from(value: #struct_name #ty_generics) -> Self {
+                    Self {
+                        _marker: std::marker::PhantomData,
+                        #(#builder_fields),*
+                    }
+                }
+            }
+
+            impl #impl_generics #struct_name #ty_generics #where_clause {
+                #vis fn to_builder(self) -> #builder_name<#(#set_args),*> {
+                    self.into()
+                }
+            }
+        )
     }
 
     /// Generate the build function for the builder struct
     ///
     ///  If `use_defaults` is set to true, use `unwrap_or_default()` to
     ///  unwrap the builder's fields
-    fn build_fn(&self, use_defaults: bool) -> proc_macro2::TokenStream {
-        let fields = self.fields.iter().map(|f| {
+    ///
+    ///  Field validators run on each value before the struct is constructed,
+    ///  the struct's validator runs on the constructed value.
+    ///
+    ///  Mutable builders are borrowed and clone their fields, checking the
+    ///  constraints of the groups at runtime.
+    ///
+    ///  The struct is returned directly when building can't fail.
+    fn build_fn(&self) -> proc_macro2::TokenStream {
+        if self.constant || self.function.is_some() {
+            return self.infallible_build_fn();
+        }
+
+        let (error_name, build_fn) = (self.error_name(), &self.build_fn);
+        let (receiver, take) = match self.pattern {
+            Pattern::Owned => (quote!(mut self), quote!(take)),
+            Pattern::Mutable => (quote!(&self), quote!(clone)),
+        };
+        let group_checks = self
+            .groups
+            .iter()
+            .filter(|_| self.runtime_checked())
+            .map(|g| {
+                let variant = g.error_variant();
+                g.runtime_check(quote!(#error_name::#variant))
+            });
+        let values = self.fields.iter().map(|f| {
             let name = &f.ident;
-            if use_defaults {
-                quote!(#name: self.#name.take().unwrap_or_default())
-            } else {
-                let error_message = format!("missing field {}", maybe_ident_to_string(name));
-                quote!(#name: self.#name.take().ok_or_else(|| #error_message)?)
+            let cfgs = &f.cfgs;
+
+            // Nested fields first surface the error of their builder
+            let taken = match f.nested {
+                Some(_) => {
+                    let variant = f.nested_variant();
+                    quote!(self.#name.#take().transpose().map_err(#error_name::#variant)?)
+                }
+                None => quote!(self.#name.#take()),
+            };
+            let value = match &f.kind {
+                FieldKind::Optional(_) => taken,
+                FieldKind::Default(Some(default)) => {
+                    quote!(#taken.unwrap_or_else(|| #default))
+                }
+                FieldKind::Default(None) => quote!(#taken.unwrap_or_default()),
+                // Skipped fields aren't stored in the builder
+                FieldKind::Skipped(Some(skip)) => quote!(#skip),
+                FieldKind::Skipped(None) => quote!(Default::default()),
+                FieldKind::Required if self.use_defaults => quote!(#taken.unwrap_or_default()),
+                FieldKind::Required => {
+                    let variant = f.missing_variant();
+                    quote!(#taken.ok_or(#error_name::#variant)?)
+                }
+            };
+            let validation = f.validate.as_ref().map(|validate| {
+                let variant = f.invalid_variant();
+                quote!(#validate(&#name).map_err(|e| #error_name::#variant(e.to_string()))?;)
+            });
+
+            let validation = validation.map(|v| quote!(#(#cfgs)* #v));
+
+            quote!(
+                #(#cfgs)*
+                let #name = #value;
+                #validation
+            )
+        });
+        let validation = self.validate.as_ref().map(
+            |validate| quote!(#validate(&value).map_err(|e| #error_name::Invalid(e.to_string()))?;),
+        );
+
+        let struct_name = &self.struct_name;
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        let construct = self.construct();
+        let (output, built) = match self.fallible() {
+            true => (
+                quote!(Result<#struct_name #ty_generics, #error_name>),
+                quote!(Ok(value)),
+            ),
+            false => (quote!(#struct_name #ty_generics), quote!(value)),
+        };
+
+        quote!(
+            // Deprecated fields are still set on the struct
+            #[allow(deprecated)]
+            pub fn #build_fn(#receiver) -> #output {
+                #(#group_checks)*
+                #(#values)*
+                let value = #construct;
+                #validation
+                #built
+            }
+        )
+    }
+
+    /// Generate the statements moving the fields of a const builder out of
+    /// it, into locals named after the fields. The provided field is moved
+    /// into `__previous` instead.
+    ///
+    /// Moving out of `self` or out of an `Option` leaves a value whose
+    /// destructor can't be evaluated in a const context, so the builder is
+    /// wrapped in `ManuallyDrop` and each of its fields is read once.
+    fn const_take(&self, previous: Option<&Ident>) -> proc_macro2::TokenStream {
+        let reads = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            let local = match previous {
+                Some(previous) if previous == name => quote!(__previous),
+                _ => quote!(#name),
+            };
+            quote!(
+                #(#cfgs)*
+                let #local = unsafe { std::ptr::read(std::ptr::addr_of!((*__builder).#name)) };
+            )
+        });
+
+        quote!(
+            let __builder = std::mem::ManuallyDrop::new(self);
+            let __builder = &__builder as *const std::mem::ManuallyDrop<Self> as *const Self;
+            // SAFETY: the builder is never dropped and each field is read once
+            #(#reads)*
+        )
+    }
+
+    /// Generate the body of a const setter, rebuilding the builder with the
+    /// provided value for the field. The field's previous value can't be
+    /// dropped in a const context and is forgotten.
+    fn const_rebuild(
+        &self,
+        field: &BuilderField,
+        value: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        let builder_name = &self.builder_name;
+        let take = self.const_take(Some(&field.ident));
+        let builder_fields = self.set_fields().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            match name == &field.ident {
+                true => quote!(#name: #value),
+                false => quote!(#(#cfgs)* #name),
             }
         });
 
+        quote!(
+            #take
+            std::mem::forget(__previous);
+            #builder_name {
+                _marker: std::marker::PhantomData,
+                #(#builder_fields),*
+            }
+        )
+    }
+
+    /// Generate the build function for a const builder, or the call function
+    /// for a function builder. Neither can fail: it moves the values out of
+    /// the builder, since `Option::take` can't drop the builder's fields in a
+    /// const context, and panics if a field is missing, which the builder's
+    /// typestate already rules out.
+    fn infallible_build_fn(&self) -> proc_macro2::TokenStream {
+        let build_fn = &self.build_fn;
+        let take = self.constant.then(|| self.const_take(None));
+        let values = self.fields.iter().map(|f| {
+            let (name, cfgs) = (&f.ident, &f.cfgs);
+            let value = match &f.kind {
+                // Const builders' fields are already moved out of the builder
+                FieldKind::Default(Some(default)) if self.constant => {
+                    quote!(match #name.is_some() {
+                        true => #name.unwrap(),
+                        false => {
+                            std::mem::forget(#name);
+                            #default
+                        }
+                    })
+                }
+                FieldKind::Required if self.constant => {
+                    let message = format!("missing field {}", f.display_name());
+                    quote!(#name.expect(#message))
+                }
+                FieldKind::Optional(_) if self.constant => quote!(#name),
+                FieldKind::Optional(_) => quote!(self.#name),
+                FieldKind::Default(Some(default)) => quote!(match self.#name {
+                    Some(value) => value,
+                    None => #default,
+                }),
+                FieldKind::Default(None) => quote!(self.#name.unwrap_or_default()),
+                FieldKind::Skipped(Some(skip)) => quote!(#skip),
+                FieldKind::Skipped(None) => quote!(Default::default()),
+                FieldKind::Required => {
+                    let message = format!("missing field {}", f.display_name());
+                    quote!(match self.#name {
+                        Some(value) => value,
+                        None => panic!(#message),
+                    })
+                }
+            };
+
+            quote!(
+                #(#cfgs)*
+                let #name = #value;
+            )
+        });
+
         let struct_name = &self.struct_name;
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        let constness = self.constant.then(|| quote!(const));
+        let (asyncness, output, construct) = match &self.function {
+            Some(function) => {
+                let names = self.fields.iter().map(|f| &f.ident);
+                let (path, receiver) = match &function.method {
+                    Some(method) => {
+                        let self_ty = &method.self_ty;
+                        let receiver = method.receiver.as_ref().map(|_| quote!(self.__receiver,));
+                        (quote!(<#self_ty>::#struct_name), receiver)
+                    }
+                    None => (quote!(#struct_name), None),
+                };
+                let call = match function.asyncness {
+                    Some(_) => quote!(#path(#receiver #(#names),*).await),
+                    None => quote!(#path(#receiver #(#names),*)),
+                };
+                (function.asyncness, &function.output, call)
+            }
+            None => (
+                None,
+                &syn::parse_quote!(#struct_name #ty_generics),
+                self.construct(),
+            ),
+        };
 
         quote!(
-            pub fn build(mut self) -> Result<#struct_name, Box<dyn std::error::Error>> {
-                Ok(#struct_name {
-                    #(#fields),*
-                })
+            #[allow(deprecated)]
+            pub #constness #asyncness fn #build_fn(self) -> #output {
+                #take
+                #(#values)*
+                #construct
+            }
+        )
+    }
+
+    /// Generate the expression constructing the struct or variant from the
+    /// local variables holding the fields' values
+    fn construct(&self) -> proc_macro2::TokenStream {
+        let struct_name = &self.struct_name;
+        let path = match &self.variant {
+            Some(variant) => quote!(#struct_name::#variant),
+            None => quote!(#struct_name),
+        };
+        let names = self.fields.iter().map(|f| &f.ident);
+
+        match self.style {
+            FieldsStyle::Named => {
+                let cfgs = self.fields.iter().map(|f| &f.cfgs);
+                quote!(#path { #(#(#cfgs)* #names),* })
             }
+            FieldsStyle::Unnamed => quote!(#path(#(#names),*)),
+            FieldsStyle::Unit => path,
+        }
+    }
+
+    /// Generate the error enum returned by the build function, with one
+    /// variant per field that can be missing and one per validator.
+    ///
+    /// If `use_defaults` is set to true, no field can be missing.
+    fn error_enum(&self) -> proc_macro2::TokenStream {
+        let (error_name, vis) = (self.error_name(), &self.builder_vis);
+        let struct_name = &self.struct_name;
+
+        let required_fields = self.fields.iter().filter(|f| f.is_required());
+        let missing = required_fields.filter(|_| !self.use_defaults).map(|f| {
+            let variant = f.missing_variant();
+            let message = format!("missing field {}", f.display_name());
+            (
+                quote!(#variant),
+                quote!(Self::#variant => f.write_str(#message)),
+            )
+        });
+        let invalid = self
+            .fields
+            .iter()
+            .filter(|f| f.validate.is_some())
+            .map(|f| {
+                let (variant, cfgs) = (f.invalid_variant(), &f.cfgs);
+                let message = format!("invalid field {}: {{}}", f.display_name());
+                (
+                    quote!(#(#cfgs)* #variant(String)),
+                    quote!(#(#cfgs)* Self::#variant(ref e) => write!(f, #message, e)),
+                )
+            });
+        let nested = self.fields.iter().filter_map(|f| {
+            let (variant, cfgs) = (f.nested_variant(), &f.cfgs);
+            let error = &f.nested.as_ref()?.error;
+            let message = format!("invalid field {}: {{}}", f.display_name());
+            Some((
+                quote!(#(#cfgs)* #variant(#error)),
+                quote!(#(#cfgs)* Self::#variant(ref e) => write!(f, #message, e)),
+            ))
+        });
+        let groups = self
+            .groups
+            .iter()
+            .filter(|_| self.runtime_checked())
+            .map(|g| {
+                let variant = g.error_variant();
+                let message = g.message(&self.builder_name);
+                (
+                    quote!(#variant),
+                    quote!(Self::#variant => f.write_str(#message)),
+                )
+            });
+        let invalid_struct = self.validate.as_ref().map(|_| {
+            let message = format!("invalid {}: {{}}", struct_name);
+            (
+                quote!(Invalid(String)),
+                quote!(Self::Invalid(ref e) => write!(f, #message, e)),
+            )
+        });
+
+        let (variants, messages): (Vec<_>, Vec<_>) = missing
+            .chain(invalid)
+            .chain(nested)
+            .chain(groups)
+            .chain(invalid_struct)
+            .unzip();
+
+        quote!(
+            #[derive(Debug, Clone, PartialEq)]
+            #[allow(clippy::enum_variant_names)]
+            #vis enum #error_name {
+                #(#variants),*
+            }
+
+            impl std::fmt::Display for #error_name {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    match *self {
+                        #(#messages),*
+                    }
+                }
+            }
+
+            impl std::error::Error for #error_name {}
         )
     }
 }
 
-/// Get the field name. Field's identifier by default or the
-/// rename attribute if it exists.
-fn get_field_name(f: &Field) -> String {
-    let rename_attr = f.attrs.iter().find(|attr| attr.path().is_ident("rename"));
-    rename_attr
-        .and_then(get_renamed_field)
-        .or_else(|| f.ident.clone())
-        .map(|i| i.to_string())
-        .unwrap_or_default()
+/// Check that no two setters or getters of the builder share a name, which
+/// renaming a field or naming an item setter can cause
+fn check_setter_names(
+    fields: &[BuilderField],
+    setter_prefix: &str,
+    pattern: Pattern,
+    reserved: &[String],
+) -> syn::Result<()> {
+    let mut names: std::collections::HashSet<_> = reserved.iter().cloned().collect();
+    for f in fields.iter().filter(|f| !f.is_skipped()) {
+        let setter = format!("{}{}", setter_prefix, f.setter_name);
+        let opt_setter = match f.kind {
+            FieldKind::Optional(_) if pattern == Pattern::Mutable || !f.has_state() => {
+                Some(format!("{}_opt", setter))
+            }
+            _ => None,
+        };
+        let each_setter = f.each.as_ref().map(|each| each.name.to_string());
+
+        let (getter, has) = f.getter_names(setter_prefix, reserved);
+
+        for name in [Some(setter), opt_setter, each_setter]
+            .into_iter()
+            .flatten()
+            .chain([getter.to_string(), has.to_string()])
+        {
+            if !names.insert(name.clone()) {
+                let message = format!("duplicate builder method `{}`", name);
+                return Err(match &f.rename {
+                    Some(rename) => syn::Error::new_spanned(rename, message),
+                    None => syn::Error::new_spanned(&f.ident, message),
+                });
+            }
+        }
+    }
+    Ok(())
 }
 
-/// Get the renamed field from the attribute
-fn get_renamed_field(attr: &Attribute) -> Option<Ident> {
-    match attr.meta {
-        syn::Meta::List(ref list) => {
-            let name = list.tokens.to_string();
-            Some(Ident::new(&name, name.span()))
-        }
-        syn::Meta::NameValue(MetaNameValue {
-            value:
-                Expr::Lit(ExprLit {
-                    lit: Lit::Str(ref str),
-                    ..
-                }),
-            ..
-        }) => {
-            let name = str.value();
-            Some(Ident::new(&name, name.span()))
+/// Check that the variants of the builder's error enum don't clash, since
+/// they are named after the fields and groups
+fn check_error_variants(
+    fields: &[BuilderField],
+    groups: &[FieldGroup],
+    attributes: &StructAttributes,
+) -> syn::Result<()> {
+    let mut variants = std::collections::HashSet::new();
+    if attributes.validate.is_some() {
+        variants.insert(format_ident!("Invalid"));
+    }
+
+    for f in fields {
+        let missing = (f.is_required() && !attributes.use_defaults).then(|| f.missing_variant());
+        let invalid = f.validate.as_ref().map(|_| f.invalid_variant());
+        let nested = f.nested.as_ref().map(|_| f.nested_variant());
+        for variant in [missing, invalid, nested].into_iter().flatten() {
+            if !variants.insert(variant.clone()) {
+                return Err(syn::Error::new_spanned(
+                    &f.ident,
+                    format!("duplicate builder error variant `{}`", variant),
+                ));
+            }
         }
-        _ => None,
     }
+
+    let runtime_checked = attributes.pattern == Pattern::Mutable || attributes.serde;
+    for group in groups.iter().filter(|_| runtime_checked) {
+        let variant = group.error_variant();
+        if !variants.insert(variant.clone()) {
+            return Err(syn::Error::new_spanned(
+                &group.name,
+                format!("duplicate builder error variant `{}`", variant),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Get the names of the methods generated on the builder besides the
+/// fields' ones, including those of the traits it implements
+fn reserved_names(build_fn: &Ident, attributes: &StructAttributes) -> Vec<String> {
+    let mut names = vec![build_fn.to_string()];
+    names.extend(["finish", "clone", "fmt", "default"].map(String::from));
+    if attributes.constant {
+        names.push("new".to_string());
+    }
+    if attributes.serde {
+        names.extend(["merge", "deserialize"].map(String::from));
+    }
+    names
 }
 
-/// Convert an optional ident to a string
-fn maybe_ident_to_string(maybe_ident: &Option<Ident>) -> String {
-    maybe_ident
-        .as_ref()
-        .map(|i| i.to_string())
-        .unwrap_or_default()
+/// Check that the builder only uses options that can be evaluated in a const
+/// context
+fn check_const(fields: &[BuilderField], attributes: &StructAttributes) -> syn::Result<()> {
+    let unsupported = |option: &str| format!("const builders don't support {}", option);
+    if attributes.pattern == Pattern::Mutable {
+        return Err(syn::Error::new(
+            attributes.key_span("pattern"),
+            unsupported("the mutable pattern"),
+        ));
+    }
+    // Merging and the runtime checks of a deserialized builder aren't const
+    if attributes.serde {
+        return Err(syn::Error::new(
+            attributes.key_span("serde"),
+            unsupported("serde"),
+        ));
+    }
+    if let Some(validate) = &attributes.validate {
+        return Err(syn::Error::new_spanned(validate, unsupported("validate")));
+    }
+
+    for f in fields {
+        let option = match f.kind {
+            _ if f.conversion.is_some() => Some("into and try_into"),
+            _ if f.each.is_some() => Some("each"),
+            _ if f.validate.is_some() => Some("validate"),
+            _ if f.nested.is_some() => Some("nested"),
+            FieldKind::Default(None) => Some("default without an expression"),
+            FieldKind::Skipped(None) => Some("skip without an expression"),
+            _ => None,
+        };
+        if let Some(option) = option {
+            return Err(syn::Error::new_spanned(&f.ty, unsupported(option)));
+        }
+    }
+    Ok(())
+}
+
+/// Generate the setter adding a single item to a collection field
+fn each_setter_fn(
+    f: &BuilderField,
+    each: &EachSetter,
+    pattern: Pattern,
+) -> proc_macro2::TokenStream {
+    let name = &f.ident;
+    let fn_name = &each.name;
+    let (params, item) = match each.item.as_slice() {
+        [key, value] => (quote!(key: #key, value: #value), quote!((key, value))),
+        items => {
+            let item = &items[0];
+            (quote!(#fn_name: #item), quote!(#fn_name))
+        }
+    };
+
+    let (receiver, ret) = match pattern {
+        Pattern::Owned => (quote!(mut self), quote!(Self)),
+        Pattern::Mutable => (quote!(&mut self), quote!(&mut Self)),
+    };
+
+    quote!(
+        pub fn #fn_name(#receiver, #params) -> #ret {
+            self.#name
+                .get_or_insert_with(Default::default)
+                .extend(Some(#item));
+            self
+        }
+    )
+}
+
+/// Generate a setter function for the field, applying the field's conversion
+/// to the value before running the body.
+///
+/// Nested fields take a closure driving their builder instead, and store the
+/// result of building it.
+fn setter_fn(
+    f: &BuilderField,
+    fn_name: &Ident,
+    constness: &Option<proc_macro2::TokenStream>,
+    receiver: proc_macro2::TokenStream,
+    ret: proc_macro2::TokenStream,
+    body: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let name = &f.ident;
+    let value_ty = f.value_ty();
+
+    if let Some(nested) = &f.nested {
+        let (builder, ready) = (&nested.builder, &nested.ready);
+        return quote!(
+            pub fn #fn_name<__Built: #ready<Output = #value_ty>>(
+                #receiver,
+                #name: impl FnOnce(#builder) -> __Built,
+            ) -> #ret {
+                let #name = #ready::finish(#name(Default::default()));
+                #body
+            }
+        );
+    }
+
+    match f.conversion {
+        None => quote!(
+            pub #constness fn #fn_name(#receiver, #name: #value_ty) -> #ret {
+                #body
+            }
+        ),
+        Some(Conversion::Into) => quote!(
+            pub fn #fn_name(#receiver, #name: impl Into<#value_ty>) -> #ret {
+                let #name = #name.into();
+                #body
+            }
+        ),
+        Some(Conversion::TryInto) => quote!(
+            pub fn #fn_name<__Value: TryInto<#value_ty>>(
+                #receiver,
+                #name: __Value,
+            ) -> Result<#ret, <__Value as TryInto<#value_ty>>::Error> {
+                let #name = #name.try_into()?;
+                Ok(#body)
+            }
+        ),
+    }
+}
+
+/// Convert an upper camel case identifier to snake case
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i != 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
 }
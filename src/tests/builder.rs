@@ -48,3 +48,1062 @@ fn test_rename_builder_function_attribute() {
         .with_descendents(vec!["Bob".to_string(), "Carol".to_string()])
         .build();
 }
+
+#[test]
+fn test_generic_builder() {
+    #[derive(Builder)]
+    struct Page<'a, T: std::fmt::Display, const N: usize>
+    where
+        T: Clone,
+    {
+        title: &'a str,
+        items: [T; N],
+    }
+
+    let page = PageBuilder::default()
+        .with_title("Index")
+        .with_items([1, 2, 3])
+        .build()
+        .unwrap();
+
+    assert_eq!(page.title, "Index");
+    assert_eq!(page.items, [1, 2, 3]);
+}
+
+#[test]
+fn test_builder_setters_any_order() {
+    #[derive(Builder)]
+    struct Person {
+        name: String,
+        age: u32,
+        kids: Vec<String>,
+    }
+
+    let person = PersonBuilder::default()
+        .with_kids(vec!["Bob".to_string()])
+        .with_age(30)
+        .with_name("Alice".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(person.name, "Alice");
+    assert_eq!(person.age, 30);
+    assert_eq!(person.kids, vec!["Bob".to_string()]);
+}
+
+mod same_module {
+    use builder_macro::Builder;
+
+    #[derive(Builder)]
+    pub struct Person {
+        pub name: String,
+    }
+
+    #[derive(Builder)]
+    pub struct Pet {
+        pub name: String,
+    }
+}
+
+#[test]
+fn test_builders_in_same_module() {
+    let person = same_module::PersonBuilder::default()
+        .with_name("Alice".to_string())
+        .build()
+        .unwrap();
+    let pet = same_module::PetBuilder::default()
+        .with_name("Rex".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(person.name, "Alice");
+    assert_eq!(pet.name, "Rex");
+}
+
+#[test]
+fn test_optional_fields() {
+    #[derive(Builder)]
+    struct Request {
+        path: String,
+        query: Option<String>,
+        body: Option<Vec<u8>>,
+    }
+
+    let request = RequestBuilder::default()
+        .with_query("page=1".to_string())
+        .with_path("/users".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(request.path, "/users");
+    assert_eq!(request.query, Some("page=1".to_string()));
+    assert_eq!(request.body, None);
+
+    let request = RequestBuilder::default()
+        .with_path("/users".to_string())
+        .with_query("page=1".to_string())
+        .with_query_opt(None)
+        .with_body_opt(Some(vec![1, 2]))
+        .build()
+        .unwrap();
+
+    assert_eq!(request.query, None);
+    assert_eq!(request.body, Some(vec![1, 2]));
+}
+
+#[test]
+fn test_default_fields() {
+    #[derive(Builder)]
+    struct Client {
+        endpoint: String,
+        #[builder(default = 3)]
+        retries: u8,
+        #[builder(default)]
+        verbose: bool,
+    }
+
+    let client = ClientBuilder::default()
+        .with_endpoint("http://localhost".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(client.endpoint, "http://localhost");
+    assert_eq!(client.retries, 3);
+    assert!(!client.verbose);
+
+    let client = ClientBuilder::default()
+        .with_retries(5)
+        .with_verbose(true)
+        .with_endpoint("http://localhost".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(client.retries, 5);
+    assert!(client.verbose);
+}
+
+#[test]
+fn test_builder_error() {
+    #[derive(Builder)]
+    struct Person {
+        name: String,
+    }
+
+    let error = PersonBuilderError::MissingName;
+
+    assert_eq!(error.to_string(), "missing field name");
+    assert_eq!(
+        PersonBuilder::default()
+            .with_name("Alice".to_string())
+            .build()
+            .map(|p| p.name),
+        Ok("Alice".to_string())
+    );
+}
+
+#[test]
+fn test_into_setters() {
+    #[derive(Builder)]
+    #[builder(into)]
+    struct Person {
+        name: String,
+        nickname: Option<String>,
+        #[builder(try_into)]
+        age: u8,
+    }
+
+    let person = PersonBuilder::default()
+        .with_name("Alice")
+        .with_nickname("Ali")
+        .with_age(30u64)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    assert_eq!(person.name, "Alice");
+    assert_eq!(person.nickname, Some("Ali".to_string()));
+    assert_eq!(person.age, 30);
+    assert!(PersonBuilder::default().with_age(300u64).is_err());
+}
+
+#[test]
+fn test_each_setters() {
+    use std::collections::{HashMap, HashSet};
+
+    #[derive(Builder)]
+    struct Person {
+        name: String,
+        #[builder(each = "kid")]
+        kids: Vec<String>,
+        #[builder(each = "tag")]
+        tags: HashSet<String>,
+        #[builder(each = "score")]
+        scores: HashMap<String, u32>,
+    }
+
+    let person = PersonBuilder::default()
+        .kid("Bob".to_string())
+        .with_name("Alice".to_string())
+        .kid("Carol".to_string())
+        .tag("parent".to_string())
+        .score("chess".to_string(), 1200)
+        .build()
+        .unwrap();
+
+    assert_eq!(person.name, "Alice");
+    assert_eq!(person.kids, vec!["Bob".to_string(), "Carol".to_string()]);
+    assert!(person.tags.contains("parent"));
+    assert_eq!(person.scores.get("chess"), Some(&1200));
+
+    let person = PersonBuilder::default()
+        .with_name("Alice".to_string())
+        .build()
+        .unwrap();
+
+    assert!(person.kids.is_empty());
+    assert!(person.tags.is_empty());
+    assert!(person.scores.is_empty());
+}
+
+#[test]
+fn test_validation() {
+    fn non_empty(value: &str) -> Result<(), &'static str> {
+        if value.is_empty() {
+            return Err("must not be empty");
+        }
+        Ok(())
+    }
+
+    fn ordered(range: &Range) -> Result<(), String> {
+        if range.start >= range.end {
+            return Err(format!("{} is not before {}", range.start, range.end));
+        }
+        Ok(())
+    }
+
+    #[derive(Builder)]
+    #[builder(validate = ordered)]
+    struct Range {
+        #[builder(validate = non_empty)]
+        name: String,
+        start: u32,
+        end: u32,
+    }
+
+    let range = RangeBuilder::default()
+        .with_name("hours".to_string())
+        .with_start(1)
+        .with_end(2)
+        .build()
+        .unwrap();
+    assert_eq!(
+        (range.name.as_str(), range.start, range.end),
+        ("hours", 1, 2)
+    );
+
+    let error = RangeBuilder::default()
+        .with_name(String::new())
+        .with_start(1)
+        .with_end(2)
+        .build()
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(
+        error,
+        RangeBuilderError::InvalidName("must not be empty".to_string())
+    );
+    assert_eq!(error.to_string(), "invalid field name: must not be empty");
+
+    let error = RangeBuilder::default()
+        .with_name("hours".to_string())
+        .with_start(2)
+        .with_end(1)
+        .build()
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(
+        error,
+        RangeBuilderError::Invalid("2 is not before 1".to_string())
+    );
+}
+
+#[test]
+fn test_to_builder() {
+    #[derive(Builder)]
+    struct Person {
+        name: String,
+        age: u32,
+        nickname: Option<String>,
+    }
+
+    let alice = PersonBuilder::default()
+        .with_name("Alice".to_string())
+        .with_age(30)
+        .with_nickname("Ali".to_string())
+        .build()
+        .unwrap();
+
+    let older = alice.to_builder().with_age(31).build().unwrap();
+    assert_eq!(older.name, "Alice");
+    assert_eq!(older.age, 31);
+    assert_eq!(older.nickname, Some("Ali".to_string()));
+
+    let renamed = PersonBuilder::from(older)
+        .with_name("Bob".to_string())
+        .build()
+        .unwrap();
+    assert_eq!(renamed.name, "Bob");
+    assert_eq!(renamed.age, 31);
+}
+
+#[test]
+fn test_tuple_struct_builder() {
+    #[derive(Builder)]
+    struct Point(u32, #[builder(setter = "y")] u32, Option<String>);
+
+    let point = PointBuilder::default()
+        .with_y(2)
+        .with_0(1)
+        .with_2("origin".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        (point.0, point.1, point.2),
+        (1, 2, Some("origin".to_string()))
+    );
+    assert_eq!(
+        PointBuilderError::MissingField0.to_string(),
+        "missing field 0"
+    );
+}
+
+#[test]
+fn test_enum_builder() {
+    #[derive(Debug, PartialEq, Builder)]
+    enum Shape<T> {
+        Circle { radius: T },
+        Rectangle(T, T),
+        Empty,
+    }
+
+    let circle = Shape::circle_builder().with_radius(1.5).build().unwrap();
+    let rectangle = Shape::rectangle_builder()
+        .with_1(3)
+        .with_0(2)
+        .build()
+        .unwrap();
+    let empty = Shape::<u8>::empty_builder().build().unwrap();
+
+    assert_eq!(circle, Shape::Circle { radius: 1.5 });
+    assert_eq!(rectangle, Shape::Rectangle(2, 3));
+    assert_eq!(empty, Shape::Empty);
+}
+
+mod nested {
+    use builder_macro::Builder;
+
+    fn positive(zip: &u32) -> Result<(), &'static str> {
+        if *zip == 0 {
+            return Err("must be positive");
+        }
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Builder)]
+    pub struct Address {
+        pub street: String,
+        #[builder(validate = positive)]
+        pub zip: u32,
+    }
+
+    #[derive(Builder)]
+    pub struct Person {
+        pub name: String,
+        #[builder(nested)]
+        pub address: Address,
+        #[builder(nested)]
+        pub work_address: Option<Address>,
+    }
+
+    #[derive(Builder)]
+    pub struct Shipment {
+        pub address: String,
+        #[builder(nested)]
+        pub missing_address: Address,
+    }
+}
+
+#[test]
+fn test_nested_builder() {
+    use nested::*;
+
+    let person = PersonBuilder::default()
+        .with_name("Alice".to_string())
+        .with_address(|a| a.with_street("Main Street".to_string()).with_zip(1000))
+        .build()
+        .unwrap();
+
+    assert_eq!(person.name, "Alice");
+    assert_eq!(
+        person.address,
+        Address {
+            street: "Main Street".to_string(),
+            zip: 1000
+        }
+    );
+    assert_eq!(person.work_address, None);
+
+    let person = person
+        .to_builder()
+        .with_work_address(|a| a.with_zip(2000).with_street("Side Street".to_string()))
+        .build()
+        .unwrap();
+
+    assert_eq!(person.work_address.map(|a| a.zip), Some(2000));
+
+    let error = PersonBuilder::default()
+        .with_name("Alice".to_string())
+        .with_address(|a| a.with_street("Main Street".to_string()).with_zip(0))
+        .build()
+        .map(|_| ())
+        .unwrap_err();
+
+    assert_eq!(
+        error,
+        PersonBuilderError::AddressBuilder(AddressBuilderError::InvalidZip(
+            "must be positive".to_string()
+        ))
+    );
+
+    let shipment = ShipmentBuilder::default()
+        .with_missing_address(|a| a.with_street("Main Street".to_string()).with_zip(1000))
+        .with_address("Side Street".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(shipment.address, "Side Street");
+    assert_eq!(shipment.missing_address.zip, 1000);
+    assert_eq!(
+        ShipmentBuilderError::MissingAddress.to_string(),
+        "missing field address"
+    );
+}
+
+#[test]
+fn test_field_groups() {
+    #[derive(Builder)]
+    struct Account {
+        user: String,
+        #[builder(group = "auth", exclusive)]
+        password: Option<String>,
+        #[builder(group = "auth")]
+        token: Option<String>,
+        #[builder(group = "contact", at_least_one)]
+        email: Option<String>,
+        #[builder(group = "contact")]
+        phone: Option<String>,
+    }
+
+    let account = AccountBuilder::default()
+        .with_user("alice".to_string())
+        .with_token("secret".to_string())
+        .with_phone("555-0100".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(account.user, "alice");
+    assert_eq!(account.password, None);
+    assert_eq!(account.token, Some("secret".to_string()));
+    assert_eq!(account.email, None);
+    assert_eq!(account.phone, Some("555-0100".to_string()));
+
+    #[derive(Builder)]
+    struct Contact {
+        #[builder(group = "contact", at_least_one)]
+        email: Option<String>,
+        #[builder(group = "contact")]
+        phone: Option<String>,
+    }
+
+    let contact = ContactBuilder::default()
+        .with_phone("555-0100".to_string())
+        .build()
+        .unwrap();
+    let contact = contact
+        .to_builder()
+        .with_email("alice@example.com".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(contact.email, Some("alice@example.com".to_string()));
+    assert_eq!(contact.phone, Some("555-0100".to_string()));
+}
+
+#[test]
+fn test_mutable_builder() {
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder(pattern = "mutable")]
+    struct Request {
+        url: String,
+        #[builder(each = "header")]
+        headers: Vec<String>,
+        timeout: Option<u64>,
+        #[builder(group = "body", exclusive)]
+        text: Option<String>,
+        #[builder(group = "body")]
+        bytes: Option<Vec<u8>>,
+    }
+
+    let mut builder = RequestBuilder::default();
+    builder.with_url("https://example.com".to_string());
+    for header in ["accept", "user-agent"] {
+        builder.header(header.to_string());
+    }
+    if builder.build().is_ok() {
+        builder.with_timeout(30);
+    }
+
+    let request = builder.build().unwrap();
+
+    assert_eq!(request.url, "https://example.com");
+    assert_eq!(request.headers, vec!["accept", "user-agent"]);
+    assert_eq!(request.timeout, Some(30));
+
+    builder
+        .with_text("hello".to_string())
+        .with_bytes(vec![0, 1]);
+
+    assert_eq!(builder.build(), Err(RequestBuilderError::BodyGroup));
+    assert_eq!(
+        RequestBuilder::default().build(),
+        Err(RequestBuilderError::MissingUrl)
+    );
+}
+
+#[test]
+fn test_forwarded_field_attributes() {
+    #[derive(Builder)]
+    struct Server {
+        /// The host the server listens on
+        host: String,
+        /// The port the server listens on
+        #[deprecated = "the port is picked by the system"]
+        #[builder(default = 8080)]
+        port: u16,
+        #[cfg(any())]
+        #[builder(default)]
+        tls: bool,
+        #[cfg(test)]
+        #[builder(default)]
+        workers: usize,
+    }
+
+    #[allow(deprecated)]
+    let server = ServerBuilder::default()
+        .with_host("localhost".to_string())
+        .with_port(3000)
+        .with_workers(4)
+        .build()
+        .unwrap();
+
+    assert_eq!(server.host, "localhost");
+    assert_eq!(server.workers, 4);
+    #[allow(deprecated)]
+    let port = server.port;
+    assert_eq!(port, 3000);
+}
+
+#[test]
+fn test_builder_customization() {
+    mod config {
+        use builder_macro::Builder;
+
+        #[derive(Builder, Debug, PartialEq)]
+        #[builder(
+            name = "ConfigFactory",
+            vis = "pub(crate)",
+            prefix = "",
+            build_fn = "finish"
+        )]
+        pub struct Config {
+            pub path: String,
+            #[builder(default)]
+            pub verbose: bool,
+        }
+    }
+
+    let config = config::ConfigFactory::default()
+        .path("/etc/app".to_string())
+        .verbose(true)
+        .finish()
+        .unwrap();
+
+    assert_eq!(
+        config,
+        config::Config {
+            path: "/etc/app".to_string(),
+            verbose: true
+        }
+    );
+    assert_eq!(
+        config::ConfigFactoryError::MissingPath.to_string(),
+        "missing field path"
+    );
+}
+
+#[test]
+fn test_skipped_fields() {
+    use std::marker::PhantomData;
+
+    #[derive(Builder)]
+    struct Cache<K> {
+        capacity: usize,
+        #[builder(skip)]
+        hits: u64,
+        #[builder(skip = vec![0; 4])]
+        buckets: Vec<u8>,
+        #[builder(skip)]
+        _key: PhantomData<K>,
+    }
+
+    let cache = CacheBuilder::<String>::default()
+        .with_capacity(16)
+        .build()
+        .unwrap();
+
+    assert_eq!(cache.capacity, 16);
+    assert_eq!(cache.hits, 0);
+    assert_eq!(cache.buckets, vec![0; 4]);
+}
+
+#[test]
+fn test_const_builder() {
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder(const)]
+    struct Color {
+        name: &'static str,
+        rgb: (u8, u8, u8),
+        #[builder(default = 255)]
+        alpha: u8,
+        hex: Option<&'static str>,
+    }
+
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder(const)]
+    enum Shape {
+        Circle { radius: u32 },
+    }
+
+    const RED: Color = ColorBuilder::new()
+        .with_rgb((255, 0, 0))
+        .with_name("red")
+        .with_hex("#ff0000")
+        .build();
+    static PALETTE: [Color; 2] = [
+        RED,
+        ColorBuilder::new()
+            .with_name("black")
+            .with_rgb((0, 0, 0))
+            .with_alpha(0)
+            .build(),
+    ];
+    const CIRCLE: Shape = Shape::circle_builder().with_radius(2).build();
+
+    assert_eq!(PALETTE[0].name, "red");
+    assert_eq!(PALETTE[0].alpha, 255);
+    assert_eq!(PALETTE[0].hex, Some("#ff0000"));
+    assert_eq!(PALETTE[1].alpha, 0);
+    assert_eq!(PALETTE[1].hex, None);
+    assert_eq!(CIRCLE, Shape::Circle { radius: 2 });
+
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder(const)]
+    struct Labeled<T> {
+        value: T,
+        #[builder(default = String::new())]
+        label: String,
+        note: Option<T>,
+    }
+
+    const EMPTY: Labeled<Vec<u8>> = LabeledBuilder::new().with_value(Vec::new()).build();
+    let labeled = LabeledBuilder::new()
+        .with_label("first".to_string())
+        .with_value("a".to_string())
+        .with_note_opt(Some("b".to_string()))
+        .with_label("second".to_string())
+        .build();
+
+    assert_eq!(
+        EMPTY,
+        Labeled {
+            value: Vec::new(),
+            label: String::new(),
+            note: None
+        }
+    );
+    assert_eq!(labeled.value, "a");
+    assert_eq!(labeled.label, "second");
+    assert_eq!(labeled.note, Some("b".to_string()));
+}
+
+#[test]
+fn test_serde_builder() {
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder(serde)]
+    struct Settings {
+        host: String,
+        port: u16,
+        #[builder(default)]
+        debug: bool,
+        log_file: Option<String>,
+    }
+
+    let defaults = SettingsBuilder::default()
+        .with_host("localhost".to_string())
+        .with_port(8080);
+    let file: SettingsBuilder =
+        serde_json::from_str(r#"{ "port": 9000, "log_file": "app.log" }"#).unwrap();
+    let cli: SettingsBuilder = serde_json::from_str(r#"{ "debug": true }"#).unwrap();
+
+    let settings = SettingsBuilder::default()
+        .merge(defaults)
+        .merge(file)
+        .merge(cli)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        settings,
+        Settings {
+            host: "localhost".to_string(),
+            port: 9000,
+            debug: true,
+            log_file: Some("app.log".to_string()),
+        }
+    );
+
+    let partial: SettingsBuilder = serde_json::from_str(r#"{ "host": "example.com" }"#).unwrap();
+    assert_eq!(partial.build(), Err(SettingsBuilderError::MissingPort));
+}
+
+#[test]
+fn test_builder_debug_and_clone() {
+    #[derive(Builder)]
+    struct Login {
+        user: String,
+        #[builder(secret)]
+        password: String,
+        attempts: Option<u32>,
+    }
+
+    struct Handle;
+
+    #[derive(Builder)]
+    struct Connection {
+        #[rename = "handle"]
+        _handle: Handle,
+    }
+
+    let builder = LoginBuilder::default().with_user("alice".to_string());
+    let complete = builder.clone().with_password("hunter2".to_string());
+
+    assert_eq!(
+        format!("{:?}", builder),
+        r#"LoginBuilder { user: Some("alice"), password: None, attempts: None }"#
+    );
+    assert_eq!(
+        format!("{:?}", complete),
+        r#"LoginBuilder { user: Some("alice"), password: Some(***), attempts: None }"#
+    );
+    assert_eq!(complete.build().unwrap().password, "hunter2");
+
+    let _ = ConnectionBuilder::default().with_handle(Handle).build();
+}
+
+#[test]
+fn test_function_builder() {
+    #[derive(Debug, PartialEq)]
+    struct Conn {
+        address: String,
+        timeout: u64,
+        retries: Vec<u64>,
+    }
+
+    #[builder_macro::builder]
+    fn connect(
+        host: String,
+        port: u16,
+        #[builder(default = 30)] timeout: u64,
+        #[builder(each = "retry")] retries: Vec<u64>,
+    ) -> Conn {
+        Conn {
+            address: format!("{}:{}", host, port),
+            timeout,
+            retries,
+        }
+    }
+
+    #[builder_macro::builder(prefix = "with_", build_fn = "run")]
+    fn sum<T: std::ops::Add<Output = T>>(left: T, right: T) -> T {
+        left + right
+    }
+
+    let conn = connect_builder()
+        .port(5432)
+        .host("localhost".to_string())
+        .retry(1)
+        .retry(5)
+        .call();
+
+    assert_eq!(
+        conn,
+        Conn {
+            address: "localhost:5432".to_string(),
+            timeout: 30,
+            retries: vec![1, 5]
+        }
+    );
+    assert_eq!(connect("db".to_string(), 1, 2, vec![]).timeout, 2);
+    assert_eq!(sum_builder().with_left(1).with_right(2).run(), 3);
+}
+
+#[test]
+fn test_method_builder() {
+    struct Client {
+        host: String,
+        sent: Vec<String>,
+    }
+
+    #[builder_macro::builder]
+    impl Client {
+        #[builder]
+        fn new(host: String) -> Self {
+            Client {
+                host,
+                sent: Vec::new(),
+            }
+        }
+
+        #[builder]
+        fn url(&self, path: &str, #[builder(default)] secure: bool) -> String {
+            let scheme = if secure { "https" } else { "http" };
+            format!("{}://{}{}", scheme, self.host, path)
+        }
+
+        #[builder(prefix = "with_", build_fn = "send")]
+        fn push(&mut self, body: String) -> usize {
+            self.sent.push(body);
+            self.sent.len()
+        }
+
+        #[builder]
+        fn into_address(self, port: u16) -> String {
+            format!("{}:{}", self.host, port)
+        }
+    }
+
+    struct Wrapper<T>(T);
+
+    #[builder_macro::builder]
+    impl<T: Clone> Wrapper<T> {
+        #[builder]
+        fn pair<U>(&self, other: U) -> (T, U) {
+            (self.0.clone(), other)
+        }
+    }
+
+    let mut client = Client::new_builder().host("example.com".to_string()).call();
+    assert_eq!(
+        client.url_builder().path("/index").secure(true).call(),
+        "https://example.com/index"
+    );
+    assert_eq!(
+        client.push_builder().with_body("ping".to_string()).send(),
+        1
+    );
+    assert_eq!(client.sent, vec!["ping".to_string()]);
+    assert_eq!(
+        client.into_address_builder().port(80).call(),
+        "example.com:80"
+    );
+    assert_eq!(Wrapper(1).pair_builder().other("one").call(), (1, "one"));
+}
+
+#[test]
+fn test_function_builder_elided_lifetimes() {
+    #[builder_macro::builder]
+    fn greet(name: &str, times: usize) -> String {
+        name.repeat(times)
+    }
+
+    #[builder_macro::builder]
+    fn first_word(text: &str) -> &str {
+        text.split(' ').next().unwrap_or_default()
+    }
+
+    #[builder_macro::builder]
+    fn shout(text: std::borrow::Cow<'_, str>, times: usize) -> String {
+        text.to_uppercase().repeat(times)
+    }
+
+    let name = "hi".to_string();
+    assert_eq!(greet_builder().times(2).name(&name).call(), "hihi");
+    assert_eq!(first_word_builder().text("hello world").call(), "hello");
+    assert_eq!(shout_builder().text("hey".into()).times(2).call(), "HEYHEY");
+}
+
+#[test]
+fn test_builder_compile_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("test-data/builder/*.rs");
+}
+
+#[test]
+fn test_struct_entry_points() {
+    #[derive(Builder, Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder(pattern = "mutable")]
+    struct Label {
+        text: String,
+        #[builder(default)]
+        bold: bool,
+    }
+
+    let point = Point::builder().with_x(1).with_y(2).build().unwrap();
+    assert_eq!(point, Point { x: 1, y: 2 });
+
+    let point = Point::build_with(|b| b.with_y(4).with_x(3)).unwrap();
+    assert_eq!(point, Point { x: 3, y: 4 });
+
+    let label = Label::build_with(|mut b| {
+        b.with_text("title".to_string()).with_bold(true);
+        b
+    });
+    assert_eq!(
+        label,
+        Ok(Label {
+            text: "title".to_string(),
+            bold: true
+        })
+    );
+    assert_eq!(
+        Label::build_with(|b| b),
+        Err(LabelBuilderError::MissingText)
+    );
+}
+
+#[test]
+fn test_builder_defaults_any_state() {
+    #[derive(Builder, Debug, PartialEq)]
+    #[builder_defaults]
+    struct Window {
+        title: String,
+        width: u32,
+        #[builder(default = 600)]
+        height: u32,
+    }
+
+    assert_eq!(
+        Window::builder().build(),
+        Window {
+            title: String::new(),
+            width: 0,
+            height: 600
+        }
+    );
+    assert_eq!(
+        WindowBuilder::default()
+            .with_title("editor".to_string())
+            .build(),
+        Window {
+            title: "editor".to_string(),
+            width: 0,
+            height: 600
+        }
+    );
+    assert_eq!(Window::build_with(|b| b.with_width(800)).width, 800);
+}
+
+#[test]
+fn test_builder_getters() {
+    #[derive(Builder)]
+    struct Profile {
+        name: String,
+        nickname: Option<String>,
+        #[builder(default)]
+        display_name: String,
+    }
+
+    #[derive(Builder)]
+    #[builder(prefix = "")]
+    struct Tag {
+        label: String,
+    }
+
+    fn with_display_name<N>(builder: ProfileBuilder<N>) -> ProfileBuilder<N> {
+        let display_name = builder
+            .nickname()
+            .or(builder.name())
+            .cloned()
+            .unwrap_or_default();
+        builder.with_display_name(display_name)
+    }
+
+    let builder = ProfileBuilder::default();
+    assert!(!builder.has_name());
+    assert_eq!(builder.name(), None);
+
+    let builder = builder.with_name("Alice".to_string());
+    assert!(builder.has_name());
+    assert!(!builder.has_nickname());
+    assert_eq!(builder.name(), Some(&"Alice".to_string()));
+
+    let profile = with_display_name(builder).build().unwrap();
+    assert_eq!(profile.display_name, "Alice");
+
+    let tag = TagBuilder::default().label("new".to_string());
+    assert_eq!(tag.get_label(), Some(&"new".to_string()));
+    assert!(tag.has_label());
+}
+
+#[test]
+fn test_builder_getters_reserved_names() {
+    #[derive(Builder)]
+    struct Job {
+        build: u32,
+        r#type: String,
+    }
+
+    #[derive(Builder)]
+    #[builder(const)]
+    struct Version {
+        new: bool,
+    }
+
+    #[derive(Builder)]
+    #[builder(serde)]
+    struct Patch {
+        merge: bool,
+    }
+
+    let job = JobBuilder::default()
+        .with_build(3)
+        .with_type("nightly".to_string());
+    assert_eq!(job.get_build(), Some(&3));
+    assert_eq!(job.get_type(), Some(&"nightly".to_string()));
+    assert_eq!(job.build().unwrap().build, 3);
+
+    let version = VersionBuilder::new().with_new(true);
+    assert_eq!(version.get_new(), Some(&true));
+    assert!(version.build().new);
+
+    let patch = PatchBuilder::default().with_merge(true);
+    assert_eq!(patch.get_merge(), Some(&true));
+    assert!(patch.build().unwrap().merge);
+}
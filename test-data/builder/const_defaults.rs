@@ -0,0 +1,10 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+#[builder_defaults]
+#[builder(const)]
+struct Settings {
+    retries: u32,
+}
+
+fn main() {}
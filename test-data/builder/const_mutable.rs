@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+#[builder(const, pattern = "mutable")]
+struct Settings {
+    retries: u32,
+}
+
+fn main() {}
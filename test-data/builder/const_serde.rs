@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+#[builder(serde, const)]
+struct Settings {
+    retries: u32,
+}
+
+fn main() {}
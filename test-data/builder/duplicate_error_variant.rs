@@ -0,0 +1,15 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Inner {
+    value: u32,
+}
+
+#[derive(Builder)]
+struct Outer {
+    name_builder: String,
+    #[builder(nested)]
+    missing_name: Inner,
+}
+
+fn main() {}
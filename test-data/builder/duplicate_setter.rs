@@ -0,0 +1,10 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Person {
+    name: String,
+    #[rename = "name"]
+    nickname: String,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Message {
+    #[builder(group = "dest", exclusive, each = "email")]
+    emails: Vec<String>,
+    #[builder(group = "dest")]
+    phone: Option<String>,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Account {
+    #[builder(group = "auth", exclusive)]
+    password: Option<String>,
+    #[builder(group = "auth")]
+    token: Option<String>,
+}
+
+fn main() {
+    let account = AccountBuilder::default()
+        .with_password("hunter2".to_string())
+        .build()
+        .unwrap();
+    let _ = account.to_builder().with_token("secret".to_string()).build();
+}
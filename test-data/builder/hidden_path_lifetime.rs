@@ -0,0 +1,8 @@
+use std::borrow::Cow;
+
+#[builder_macro::builder]
+fn shout(text: Cow<str>, times: usize) -> String {
+    text.to_uppercase().repeat(times)
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[builder_macro::builder]
+fn greet(name: impl Into<String>) -> String {
+    name.into()
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+#[builder(prefix = "set-")]
+struct Person {
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Person {
+    #[rename = "first name"]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,25 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Contact {
+    #[builder(group = "contact", at_least_one)]
+    c0: Option<u8>,
+    #[builder(group = "contact")]
+    c1: Option<u8>,
+    #[builder(group = "contact")]
+    c2: Option<u8>,
+    #[builder(group = "contact")]
+    c3: Option<u8>,
+    #[builder(group = "contact")]
+    c4: Option<u8>,
+    #[builder(group = "contact")]
+    c5: Option<u8>,
+    #[builder(group = "contact")]
+    c6: Option<u8>,
+    #[builder(group = "contact")]
+    c7: Option<u8>,
+    #[builder(group = "contact")]
+    c8: Option<u8>,
+}
+
+fn main() {}
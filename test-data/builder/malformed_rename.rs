@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Person {
+    #[rename]
+    name: String,
+}
+
+fn main() {}
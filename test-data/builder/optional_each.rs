@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Message {
+    #[builder(each = "item")]
+    items: Option<Vec<u8>>,
+}
+
+fn main() {}
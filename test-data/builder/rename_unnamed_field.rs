@@ -0,0 +1,6 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Point(#[rename = "x"] u32, u32);
+
+fn main() {}
@@ -0,0 +1,11 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Account {
+    #[builder(group = "auth", exclusive)]
+    password: String,
+    #[builder(group = "auth")]
+    token: String,
+}
+
+fn main() {}
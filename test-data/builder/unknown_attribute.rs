@@ -0,0 +1,9 @@
+use builder_macro::Builder;
+
+#[derive(Builder)]
+struct Person {
+    #[builder(defualt)]
+    name: String,
+}
+
+fn main() {}